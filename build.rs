@@ -2,6 +2,10 @@ use std::env;
 use std::io::{Result, BufRead, BufReader, Write};
 use std::fs::File;
 use std::convert::TryFrom;
+use std::collections::BTreeMap;
+
+/// A keysym of `linux-keysymdef`: its name, value, character and whether it's deprecated
+type Entry = (String, u32, Option<char>, bool);
 
 fn main() -> Result<()> {
     let out_path = env::var("OUT_DIR").unwrap() + "/keysymdef.rs";
@@ -9,8 +13,7 @@ fn main() -> Result<()> {
     let keysymdef = BufReader::new(File::open("linux-keysymdef")?);
     let mut out = File::create(out_path)?;
 
-    writeln!(out, "{{let mut map = BiMap::new();")?;
-
+    let mut entries: Vec<Entry> = Vec::new();
     let mut in_comment_block = false;
 
     for line in keysymdef.lines() {
@@ -33,12 +36,52 @@ fn main() -> Result<()> {
         let mut elems = line.split_whitespace();
 
         let name = elems.next().unwrap();
-        let codepoint = <u32>::from_str_radix(&elems.next().unwrap()[1..], 16).unwrap();
-        let ch = <char>::try_from(codepoint).unwrap();
-        writeln!(out, "map.insert({:?}, {:?});", name, ch)?;
+        let keysym = <u32>::from_str_radix(&elems.next().unwrap()[2..], 16).unwrap();
+        let mut elem = elems.next();
+        let ch = match elem {
+            Some(codepoint) if codepoint.starts_with('U') => {
+                elem = elems.next();
+                let codepoint = <u32>::from_str_radix(&codepoint[1..], 16).unwrap();
+                Some(<char>::try_from(codepoint).unwrap())
+            }
+            _ => None,
+        };
+        let deprecated = elem == Some("deprecated");
+        entries.push((name.to_owned(), keysym, ch, deprecated));
+    }
+    entries.push(("NoSymbol".to_owned(), 0, Some('\0'), false));
+
+    // The first name and character of each keysym, and the first names of each character,
+    // the first one that isn't deprecated too
+    let mut by_keysym: BTreeMap<u32, (&str, Option<char>)> = BTreeMap::new();
+    let mut by_char: BTreeMap<char, (&str, Option<&str>)> = BTreeMap::new();
+    for (name, keysym, ch, deprecated) in &entries {
+        let first = by_keysym.entry(*keysym).or_insert((name, None));
+        first.1 = first.1.or(*ch);
+        if let Some(ch) = *ch {
+            let names = by_char.entry(ch).or_insert((name, None));
+            if !deprecated {
+                names.1 = names.1.or(Some(name));
+            }
+        }
     }
 
-    writeln!(out, "map}}")?;
+    writeln!(out, "BiMap {{")?;
+    writeln!(out, "by_name: &[")?;
+    let mut by_name: Vec<&Entry> = entries.iter().collect();
+    by_name.sort_by(|a, b| a.0.cmp(&b.0));
+    for (name, keysym, ch, deprecated) in by_name {
+        writeln!(out, "({:?}, {:#x}, {:?}, {}),", name, keysym, ch, deprecated)?;
+    }
+    writeln!(out, "],\nby_keysym: &[")?;
+    for (keysym, (name, ch)) in &by_keysym {
+        writeln!(out, "({:#x}, {:?}, {:?}),", keysym, name, ch)?;
+    }
+    writeln!(out, "],\nby_char: &[")?;
+    for (ch, (name, current)) in &by_char {
+        writeln!(out, "({:?}, {:?}, {:?}),", ch, name, current)?;
+    }
+    writeln!(out, "],\n}}")?;
 
     Ok(())
 }
\ No newline at end of file
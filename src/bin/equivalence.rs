@@ -0,0 +1,128 @@
+use klay::KlayLayout;
+use klay::emulate::{Model, Platform};
+use klay::equivalence::compare;
+use klay::klc::WinKeyLayout;
+use klay::linux::{Layout, Compose, SymbolsFile, PartialXkbSymbols, Resolver};
+
+use std::fs::{File, read_to_string};
+use std::path::Path;
+use std::env::args;
+use std::process::exit;
+
+/// Loads a layout of any format, guessing the format from the extension
+///
+/// XKB symbols files may name a section and a group as in `dk(nodeadkeys):2`,
+/// their includes are followed and an XCompose file next to them is used for their dead keys.
+/// Klay layouts belong to no platform, so they get the given one's dead keys, else Windows'.
+fn load(path: &str, platform: Option<Platform>) -> Model {
+    if path.ends_with(".toml") {
+        let layout = KlayLayout::from_str(&read_to_string(path).unwrap()).unwrap();
+        Model::from_klay(&layout, platform.unwrap_or(Platform::Windows)).unwrap_or_else(|e| {
+            eprintln!("error: {} in {}", e, path);
+            exit(1)
+        })
+    } else if path.ends_with(".klc") {
+        Model::from_klc(&WinKeyLayout::from_reader(File::open(path).unwrap()).unwrap())
+    } else if path.ends_with(".keylayout") {
+        load_keylayout(path)
+    } else {
+        let (path, group) = match path.rfind(':') {
+            Some(i) => (&path[..i], path[i+1..].parse().unwrap_or(1)),
+            None => (path, 1),
+        };
+        let (path, section) = match path.find('(') {
+            Some(i) => (&path[..i], Some(path[i+1..].trim_end_matches(')'))),
+            None => (path, None),
+        };
+        let file = SymbolsFile::parse(&read_to_string(path).unwrap()).unwrap();
+        let layout = Layout::from_file(&file).unwrap();
+        let partial = match section {
+            Some(s) => layout.get_partial(s).unwrap_or_else(|| {
+                eprintln!("error: no section `{}' in {}", s, path);
+                exit(1)
+            }),
+            None => &layout.default_partial,
+        };
+        // The file can include its own sections by its name
+        let mut resolver = Resolver::from_env();
+        let name = Path::new(path).file_stem().unwrap().to_string_lossy().into_owned();
+        resolver.add_file(name, file.clone());
+        let flattened = match resolver.flatten(partial) {
+            Ok(groups) => PartialXkbSymbols {
+                groups,
+                includes: Vec::new(),
+                .. partial.clone()
+            },
+            Err(e) => {
+                eprintln!("warning: includes of {} not followed: {}", path, e);
+                partial.clone()
+            }
+        };
+        let mut model = Model::from_xkb(&flattened.select_group(group)).unwrap_or_else(|e| {
+            eprintln!("error: {} in {}", e, path);
+            exit(1)
+        });
+        // Dead keys compose according to the compose file written next to the symbols
+        if let Ok(file) = File::open(Path::new(path).with_extension("XCompose")) {
+            model.add_compose(&Compose::from_reader(file).unwrap());
+        }
+        model
+    }
+}
+
+#[cfg(feature = "macos")]
+fn load_keylayout(path: &str) -> Model {
+    Model::from_keylayout(&klay::keylayout::parse(File::open(path).unwrap()).unwrap())
+}
+#[cfg(not(feature = "macos"))]
+fn load_keylayout(_: &str) -> Model {
+    eprintln!("error: built without macOS support");
+    exit(1)
+}
+
+fn usage() -> ! {
+    eprintln!("usage: equivalence <layout> <layout> [--platform windows|linux|macos]");
+    exit(1)
+}
+
+fn main() {
+    let mut platform = None;
+    let mut paths = Vec::new();
+    let mut args = args().skip(1);
+
+    while let Some(arg) = args.next() {
+        if arg == "--platform" {
+            platform = Some(match args.next().as_deref() {
+                Some("windows") => Platform::Windows,
+                Some("linux") => Platform::Linux,
+                Some("macos") => Platform::MacOs,
+                Some(other) => {
+                    eprintln!("error: unknown platform `{}'", other);
+                    usage()
+                }
+                None => usage(),
+            });
+        } else {
+            paths.push(arg);
+        }
+    }
+    if paths.len() != 2 {
+        usage();
+    }
+
+    let mut left = load(&paths[0], platform);
+    let mut right = load(&paths[1], platform);
+    if let Some(platform) = platform {
+        left.platform = platform;
+        right.platform = platform;
+    }
+
+    let differences = compare(&left, &right);
+    for difference in &differences {
+        println!("{}", difference);
+    }
+    if !differences.is_empty() {
+        eprintln!("{} differences", differences.len());
+        exit(1);
+    }
+}
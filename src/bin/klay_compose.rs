@@ -0,0 +1,39 @@
+use klay::KlayLayout;
+use klay::compose::{to_xcompose, to_klc};
+
+use std::fs::{File, read_to_string};
+use std::path::PathBuf;
+use std::env::args;
+
+/// Writes the compose sequences of Klay layouts as XCompose files
+/// and reports what couldn't be expressed on each platform
+fn main() {
+    for arg in args().skip(1) {
+        let layout = KlayLayout::from_str(&read_to_string(&arg).unwrap()).unwrap();
+        let sequences = layout.compose_sequences().unwrap();
+
+        let (compose, unsupported) = to_xcompose(&sequences);
+        let mut path = PathBuf::from(arg.trim_end_matches(".toml").trim_end_matches(".klay"));
+        path.set_extension("XCompose");
+        compose.write(File::create(path).unwrap()).unwrap();
+        for u in unsupported {
+            eprintln!("linux: {}", u);
+        }
+
+        let (_, unsupported) = to_klc(&sequences);
+        for u in unsupported {
+            eprintln!("windows: {}", u);
+        }
+        report_keylayout(&sequences);
+    }
+}
+
+#[cfg(feature = "macos")]
+fn report_keylayout(sequences: &[klay::compose::ComposeSequence]) {
+    let (_, _, unsupported) = klay::compose::to_keylayout(sequences);
+    for u in unsupported {
+        eprintln!("macos: {}", u);
+    }
+}
+#[cfg(not(feature = "macos"))]
+fn report_keylayout(_: &[klay::compose::ComposeSequence]) {}
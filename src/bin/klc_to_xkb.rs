@@ -1,127 +1,111 @@
 use klay::linux::*;
-use klay::klc::{Key as KlcKey, WinKeyLayout};
-use klay::convert::win_to_linux;
+use klay::klc::WinKeyLayout;
+use klay::convert::{Base, KlcToXkb};
+use klay::Metadata;
 
-use std::collections::BTreeMap;
 use std::path::PathBuf;
 use std::fs::File;
 use std::env::args;
 
+/// Converts KLC files, `--keysyms canonical|unicode|no-deprecated` choosing how characters are named
+///
+/// Dead keys get the dead keysym of their character, from the `--dead-keys` file or the built-in ones.
+/// Others are asked for, or with `--batch` are left as characters that start compose sequences.
+///
+/// Only the keys that differ from a base layout are written, the base being given with `--base`,
+/// like `dk(basic)`, or else the layout of the country of the KLC locale, or `us`.
+/// With `--standalone` there's no base and every key is written.
 fn main() {
-    for arg in args().skip(1) {
+    let mut keysyms = KeysymPolicy::default();
+    let mut converter = KlcToXkb::new();
+    let mut batch = false;
+    let mut files = Vec::new();
+    let mut args = args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--keysyms" {
+            keysyms = args.next().as_deref().and_then(KeysymPolicy::from_name).expect("unknown keysym policy");
+        } else if arg == "--dead-keys" {
+            let file = File::open(args.next().expect("no dead keys file")).unwrap();
+            converter.dead_keys = DeadKeyMap::from_reader(file).unwrap();
+        } else if arg == "--batch" {
+            batch = true;
+        } else if arg == "--base" {
+            converter.base = Base::Include(args.next().expect("no base layout"));
+        } else if arg == "--standalone" {
+            converter.base = Base::Standalone;
+        } else {
+            files.push(arg);
+        }
+    }
+
+    for arg in files {
         let mut path = PathBuf::from(arg);
         let file_klc = File::open(&path).unwrap();
         let layout = WinKeyLayout::from_reader(file_klc).unwrap();
 
-        let layout = convert(layout);
-
-        path.set_extension("");
-        let out_file = File::create(path).unwrap();
-        layout.write(out_file).unwrap();
-    }
-}
-
-fn load(p: &str) -> Layout {
-    let file = File::open(p).unwrap();
-
-    Layout::from_reader(file).unwrap()
-}
-
-fn split_to_file_partial(s: &str) -> (&str, Option<(&str)>) {
-    let mut path = s;
-    let part = s
-        .find('(')
-        .map(|i| {
-            path = &s[..i];
-            &s[i+1..s.len()-1]
-        });
-    (path, part)
-}
-
-fn default_keys(p: &str) -> BTreeMap<Key, Output> {
-    let (path, part) = split_to_file_partial(p);
-
-    let lay = load(path);
-    let part = if let Some(part) = part {
-        lay.get_partial(part).unwrap()
-    } else {
-        &lay.default_partial
-    };
+        if !batch {
+            for &c in layout.deadkeys.keys() {
+                if converter.dead_keys.get(c).is_none() {
+                    if let Some(name) = ask_dead(c) {
+                        converter.dead_keys.chars.insert(c, name);
+                    }
+                }
+            }
+        }
 
-    let mut base = if let Some(ref inc) = part.include {
-        default_keys(inc)
-    } else {
-        BTreeMap::new()
-    };
+        let metadata = Metadata {
+            name: layout.name.clone(),
+            description: layout.description.clone(),
+            locale: layout.locale_name.clone(),
+            version: layout.version.clone(),
+            author: layout.company.clone(),
+            ..Metadata::default()
+        };
+        let conversion = converter.convert(&layout).unwrap();
+        for warning in &conversion.warnings {
+            eprintln!("warning: {}", warning);
+        }
+        let layout = conversion.layout;
 
-    for (&key, output) in part.keys.iter() {
-        base.insert(key, output.clone());
+        path.set_extension("");
+        let out_file = File::create(&path).unwrap();
+        layout.write_with_keysyms(out_file, keysyms).unwrap();
+
+        // The registry entries the layout needs to be selectable
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        let entry = RegistryEntry::new(&name, &metadata, &layout);
+        path.set_extension("xml");
+        entry.write_xml(File::create(&path).unwrap()).unwrap();
+        path.set_extension("lst");
+        entry.write_lst(File::create(&path).unwrap()).unwrap();
+
+        path.set_extension("XCompose");
+        let compose_file = File::create(path).unwrap();
+        conversion.compose.write(compose_file).unwrap();
     }
-
-    base
 }
 
 use std::io::{Write, stdin, stdout};
 
-fn char_or_dead(c: char, deads: &[char]) -> Character {
-    if deads.contains(&c) {
-        println!("Deadkey `{}' detected.", c);
+/// Asks for the dead keysym of a dead key until a valid one or nothing is given
+fn ask_dead(c: char) -> Option<String> {
+    println!("Deadkey `{}' detected.", c);
+    loop {
         print!("Please enter x11 deadkey name (leave empty to ignore the deadkey): dead_");
         stdout().flush().unwrap();
         let mut line = String::new();
-        stdin().read_line(&mut line).unwrap();
-        line = line.trim().to_owned();
-
-        if line.is_empty() {
-            Character::Char(c)
-        } else {
-            Character::Dead(line.into_boxed_str())
+        if stdin().read_line(&mut line).unwrap() == 0 {
+            return None;
         }
-    } else {
-        Character::Char(c)
-    }
-}
-
-fn convert_output(win_key: KlcKey, deads: &[char]) -> Output {
-    let normal = win_key.normal.unwrap_or('\0');
-    let shift = win_key.shift.unwrap_or('\0');
-    let altgr = win_key.ctrl_alt.unwrap_or('\0');
-    let altgr_shift = win_key.shift_ctrl_alt.unwrap_or('\0');
-
-    Output {
-        normal: char_or_dead(normal, deads),
-        shift: char_or_dead(shift, deads),
-        altgr: char_or_dead(altgr, deads),
-        altgr_shift: char_or_dead(altgr_shift, deads),
-    }
-}
-
-fn convert(win_layout: WinKeyLayout) -> Layout {
-    let mut default_partial = PartialXkbSymbols::new("basic".to_owned());
-
-    default_partial.include = Some("dk(basic)".to_owned());
-    default_partial.name_group1 = Some(win_layout.name);
+        let line = line.trim();
+        let name = line.strip_prefix("dead_").unwrap_or(line);
 
-    let deads: Vec<_> = win_layout.deadkeys.keys().copied().collect();
-
-    let default_keys = default_keys("dk(basic)");
-
-    for (scan_code, win_key) in win_layout.layout {
-        let key_code = win_to_linux(scan_code);
-        let mut output = convert_output(win_key, &deads);
-
-        if let Some(def_out) = default_keys.get(&key_code) {
-            output |= def_out.clone();
-            if def_out == &output {
-                continue;
-            }
+        if name.is_empty() {
+            return None;
         }
-
-        default_partial.keys.insert(key_code, output);
-    }
-
-    Layout {
-        default_partial: default_partial,
-        partials: Vec::new(),
+        if Keysym::from_name(&format!("dead_{}", name)).is_some_and(Keysym::is_dead) {
+            return Some(name.to_owned());
+        }
+        println!("`dead_{}' is not a dead keysym.", name);
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,73 @@
+use klay::{KlayLayout, KeyboardKey, Level};
+use klay::optimize::{CostModel, Corpus, Optimizer};
+
+use std::fs::{read_to_string, write};
+use std::env::args;
+use std::process::exit;
+
+fn usage() -> ! {
+    eprintln!("usage: optimize <layout.klay.toml> <corpus.txt> [-o out.klay.toml] [--keys e01,d01,..] \
+        [--pin key|level|key:level].. [--cost cost.toml] [--iterations n] [--seed n]");
+    exit(1)
+}
+
+fn parse<T: serde::de::DeserializeOwned>(s: &str) -> T {
+    toml::Value::String(s.to_owned()).try_into().unwrap_or_else(|_| {
+        eprintln!("error: unknown key or level `{}'", s);
+        exit(1)
+    })
+}
+
+fn main() {
+    let mut args = args().skip(1);
+    let mut positional = Vec::new();
+    let mut out = None;
+    let mut keys: Option<Vec<KeyboardKey>> = None;
+    let mut pins = Vec::new();
+    let mut cost = None;
+    let mut optimizer = Optimizer::default();
+
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| usage());
+        match &*arg {
+            "-o" => out = Some(value()),
+            "--keys" => keys = Some(value().split(',').map(parse).collect()),
+            "--pin" => pins.push(value()),
+            "--cost" => cost = Some(value()),
+            "--iterations" => optimizer.iterations = value().parse().unwrap_or_else(|_| usage()),
+            "--seed" => optimizer.seed = value().parse().unwrap_or_else(|_| usage()),
+            _ => positional.push(arg),
+        }
+    }
+    if positional.len() != 2 {
+        usage();
+    }
+
+    let layout = KlayLayout::from_str(&read_to_string(&positional[0]).unwrap()).unwrap();
+    let corpus = Corpus::new(&read_to_string(&positional[1]).unwrap());
+    let model = match cost {
+        Some(path) => toml::from_str(&read_to_string(path).unwrap()).unwrap(),
+        None => CostModel::default(),
+    };
+
+    optimizer.keys = keys.unwrap_or_else(|| layout.keymap.keys().copied().collect::<Vec<_>>())
+        .into_iter().collect();
+    for pin in pins {
+        let mut split = pin.splitn(2, ':');
+        let first = split.next().unwrap();
+        match split.next() {
+            Some(level) => optimizer.pin(parse(first), parse(level)),
+            None => match toml::Value::String(first.to_owned()).try_into::<Level>() {
+                Ok(level) => optimizer.pin_level(level),
+                Err(_) => optimizer.pin_key(parse(first)),
+            },
+        };
+    }
+
+    let before = model.cost(&layout, &corpus);
+    let (layout, after) = optimizer.optimize(&layout, &corpus, &model);
+    eprintln!("cost: {} -> {}", before, after);
+
+    let out = out.unwrap_or_else(|| positional[0].trim_end_matches(".klay.toml").to_owned() + ".optimized.klay.toml");
+    write(out, layout.to_string().unwrap()).unwrap();
+}
@@ -1,14 +1,27 @@
-use klay::linux::Layout;
+use klay::linux::{Layout, SymbolsEditor};
 
-use std::fs::File;
+use std::fs::{File, read_to_string, write};
 use std::env::args;
 
+/// Writes symbols files as klay models them to `<file>.parsed`
+///
+/// With `--preserve` the model is written into the file as it was, so
+/// everything the model keeps the same is left as it is, comments included.
 fn main() {
-    for arg in args().skip(1) {
+    let preserve = args().any(|a| a == "--preserve");
+    for arg in args().skip(1).filter(|a| a != "--preserve") {
+        if preserve {
+            let src = read_to_string(&arg).unwrap();
+            let layout = Layout::from_reader(src.as_bytes()).unwrap();
+            let mut editor = SymbolsEditor::new(src).unwrap();
+            editor.update(&layout).unwrap();
+            write(arg + ".parsed", editor.as_str()).unwrap();
+            continue;
+        }
         let file = File::open(arg.clone()).unwrap();
         let layout = Layout::from_reader(file).unwrap();
 
         let out_file = File::create(arg + ".parsed").unwrap();
         layout.write(out_file).unwrap();
     }
-}
\ No newline at end of file
+}
@@ -5,8 +5,8 @@ use std::convert::TryFrom;
 
 fn main() {
     for arg in args().skip(1) {
-        let c = if arg.starts_with("U") {
-            if let Ok(codepoint) = <u32>::from_str_radix(&arg[1..], 16) {
+        let c = if let Some(hex) = arg.strip_prefix('U') {
+            if let Ok(codepoint) = <u32>::from_str_radix(hex, 16) {
                 <char>::try_from(codepoint).ok()
             } else {
                 arg.parse().ok()
@@ -0,0 +1,54 @@
+use klay::Metadata;
+use klay::linux::{Layout, Installer, RegistryEntry};
+
+use std::fs::File;
+use std::path::{Path, PathBuf};
+use std::env::args;
+use std::process::exit;
+
+const USAGE: &str = "usage: xkb_install [--root DIR] [--locale LOCALE] [--short NAME] FILE...
+       xkb_install --uninstall [--root DIR] NAME...";
+
+/// Installs XKB symbols files into `~/.config/xkb` or another XKB directory, named after the file
+fn main() {
+    let mut root = None;
+    let mut uninstall = false;
+    let mut metadata = Metadata::default();
+    let mut files = Vec::new();
+
+    let mut args = args().skip(1);
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().unwrap_or_else(|| {
+            eprintln!("{}", USAGE);
+            exit(2)
+        });
+        match &*arg {
+            "--root" => root = Some(PathBuf::from(value())),
+            "--locale" => metadata.locale = value(),
+            "--short" => metadata.short = value(),
+            "--uninstall" => uninstall = true,
+            _ => files.push(arg),
+        }
+    }
+    if files.is_empty() {
+        eprintln!("{}", USAGE);
+        exit(2);
+    }
+
+    let installer = match root {
+        Some(root) => Installer::new(root),
+        None => Installer::user().unwrap(),
+    };
+    for file in files {
+        if uninstall {
+            if !installer.uninstall(&file).unwrap() {
+                eprintln!("warning: {} isn't installed in {}", file, installer.root.display());
+            }
+            continue;
+        }
+        let layout = Layout::from_reader(File::open(&file).unwrap()).unwrap();
+        let name = Path::new(&file).file_stem().unwrap().to_string_lossy().into_owned();
+        let entry = RegistryEntry::new(&name, &metadata, &layout);
+        installer.install(&name, &layout, &entry).unwrap();
+    }
+}
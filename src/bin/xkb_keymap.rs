@@ -0,0 +1,25 @@
+use klay::linux::{Layout, SymbolsFile, Resolver};
+
+use std::fs::read_to_string;
+use std::env::args;
+use std::io::stdout;
+use std::path::Path;
+
+/// Prints the complete keymap of `file` or `file(section)`
+fn main() {
+    let arg = args().nth(1).expect("usage: xkb_keymap FILE[(SECTION)]");
+    let (path, section) = match arg.find('(') {
+        Some(i) => (&arg[..i], Some(arg[i+1..].trim_end_matches(')'))),
+        None => (&arg[..], None),
+    };
+
+    let file = SymbolsFile::parse(&read_to_string(path).unwrap()).unwrap();
+    let layout = Layout::from_file(&file).unwrap();
+
+    // Sections can include each other by the name of the file
+    let mut resolver = Resolver::from_env();
+    let name = Path::new(path).file_stem().unwrap().to_string_lossy().into_owned();
+    resolver.add_file(name, file);
+
+    layout.write_keymap(stdout(), section, &resolver).unwrap();
+}
@@ -0,0 +1,337 @@
+//! User-defined compose sequences from the `[compose]` table of a Klay layout
+//!
+//! A sequence is written as keys in angle brackets, e.g. `<Multi_key> <e> <=>`.
+//! A key is either a single character, `Uxxxx` for a codepoint, `Multi_key`
+//! or the name of a dead key from the `[special]` table.
+//! Not every target can express every sequence, so each exporter also reports what it left out.
+
+use crate::{KlayLayout, Special};
+
+use std::fmt::{self, Display};
+use std::convert::TryFrom;
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ComposeKey {
+    /// The compose key, `Multi_key` in X11
+    Multi,
+    /// A dead key from the `[special]` table and its character
+    Dead(Box<str>, char),
+    Char(char),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ComposeSequence {
+    pub keys: Vec<ComposeKey>,
+    pub output: String,
+}
+
+/// A sequence a target couldn't express
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Unsupported {
+    pub sequence: String,
+    pub reason: &'static str,
+}
+
+impl Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: {}", self.sequence, self.reason)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidSequence(pub String);
+
+impl Display for InvalidSequence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Invalid compose sequence {:?}", self.0)
+    }
+}
+
+fn parse_key(layout: &KlayLayout, key: &str) -> Option<ComposeKey> {
+    let name = key.strip_prefix('<')?.strip_suffix('>')?;
+    let mut chars = name.chars();
+
+    Some(match (chars.next()?, chars.next()) {
+        (c, None) => ComposeKey::Char(c),
+        _ if name == "Multi_key" => ComposeKey::Multi,
+        _ if layout.special.contains_key(name) => {
+            let Special::Deadkey{deadkey} = layout.special[name];
+            ComposeKey::Dead(name.into(), deadkey)
+        }
+        ('U', _) => ComposeKey::Char(char::try_from(u32::from_str_radix(&name[1..], 16).ok()?).ok()?),
+        _ => return None,
+    })
+}
+
+impl KlayLayout {
+    /// Parses the sequences of the `[compose]` table
+    pub fn compose_sequences(&self) -> Result<Vec<ComposeSequence>, InvalidSequence> {
+        self.compose.iter().map(|(seq, output)| {
+            let keys = seq.split_whitespace()
+                .map(|k| parse_key(self, k))
+                .collect::<Option<Vec<_>>>()
+                .filter(|keys| !keys.is_empty())
+                .ok_or_else(|| InvalidSequence(seq.clone()))?;
+            Ok(ComposeSequence {
+                keys,
+                output: output.clone(),
+            })
+        }).collect()
+    }
+}
+
+impl ComposeSequence {
+    #[cfg(any(feature = "linux", feature = "windows", feature = "macos"))]
+    fn unsupported(&self, reason: &'static str) -> Unsupported {
+        Unsupported {
+            sequence: self.to_string(),
+            reason,
+        }
+    }
+    /// The output as a single character if it is one
+    #[cfg(feature = "windows")]
+    fn output_char(&self) -> Option<char> {
+        let mut chars = self.output.chars();
+        match (chars.next(), chars.next()) {
+            (Some(c), None) => Some(c),
+            _ => None,
+        }
+    }
+}
+
+impl Display for ComposeSequence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, key) in self.keys.iter().enumerate() {
+            if i != 0 {
+                write!(f, " ")?;
+            }
+            match key {
+                ComposeKey::Multi => write!(f, "<Multi_key>")?,
+                ComposeKey::Dead(name, _) => write!(f, "<{}>", name)?,
+                ComposeKey::Char(c) => write!(f, "<{}>", c)?,
+            }
+        }
+        write!(f, " -> {:?}", self.output)
+    }
+}
+
+/// Turns sequences into an XCompose file
+///
+/// Dead keys are only expressible if their character has an X11 dead keysym.
+#[cfg(feature = "linux")]
+pub fn to_xcompose(sequences: &[ComposeSequence]) -> (crate::linux::Compose, Vec<Unsupported>) {
+    use crate::linux::{Compose, Sequence, char_to_name, char_to_dead};
+
+    let mut compose = Compose::new();
+    let mut unsupported = Vec::new();
+
+    'sequences: for sequence in sequences {
+        let mut keys = Vec::with_capacity(sequence.keys.len());
+        for key in &sequence.keys {
+            keys.push(match *key {
+                ComposeKey::Multi => "Multi_key".to_owned(),
+                ComposeKey::Char(c) => char_to_name(c),
+                ComposeKey::Dead(_, c) => match char_to_dead(c) {
+                    Some(dead) => format!("dead_{}", dead),
+                    None => {
+                        unsupported.push(sequence.unsupported("dead key has no X11 dead keysym"));
+                        continue 'sequences;
+                    }
+                },
+            });
+        }
+        compose.sequences.push(Sequence {
+            keys,
+            output: sequence.output.clone(),
+        });
+    }
+
+    (compose, unsupported)
+}
+
+/// Adds a `when` to an action unless the action already has one for that state
+#[cfg(feature = "macos")]
+fn add_when(actions: &mut std::collections::BTreeMap<String, Vec<crate::keylayout::When>>, id: String, when: crate::keylayout::When) {
+    use crate::keylayout::When;
+
+    let state = |w: &When| match w {
+        When::Output{state, ..} | When::Next{state, ..} => state.clone(),
+    };
+    let whens = actions.entry(id).or_default();
+    if !whens.iter().any(|w| state(w) == state(&when)) {
+        whens.push(when);
+    }
+}
+
+/// The action id, the action outside of any dead state and the text typed by a key
+#[cfg(feature = "macos")]
+fn key_action(key: &ComposeKey) -> (String, crate::keylayout::When, String) {
+    use crate::keylayout::When;
+
+    match *key {
+        ComposeKey::Multi => unreachable!(),
+        ComposeKey::Dead(ref name, c) => (name.to_string(), When::Next{state: "none".to_owned(), next: name.to_string()}, c.to_string()),
+        ComposeKey::Char(c) => (c.to_string(), When::Output{state: "none".to_owned(), output: c.to_string()}, c.to_string()),
+    }
+}
+
+/// Turns sequences into a chain of dead states in keylayout actions
+///
+/// Every character typed in a sequence gets an action with the character as its id, and every
+/// dead key an action with its name as the id; the keys of the key maps should use those actions.
+/// macOS has no compose key, so sequences starting with one can't be expressed.
+#[cfg(feature = "macos")]
+pub fn to_keylayout(sequences: &[ComposeSequence]) -> (crate::keylayout::Actions, crate::keylayout::Terminators, Vec<Unsupported>) {
+    use crate::keylayout::{Actions, Action, Terminators, When};
+    use std::collections::BTreeMap;
+
+    let mut actions: BTreeMap<String, Vec<When>> = BTreeMap::new();
+    let mut terminators: BTreeMap<String, String> = BTreeMap::new();
+    let mut unsupported = Vec::new();
+
+    for sequence in sequences {
+        let (first, rest) = match sequence.keys.split_first() {
+            Some((first @ ComposeKey::Dead(..), rest)) if !rest.is_empty() => (first, rest),
+            Some((ComposeKey::Multi, _)) => {
+                unsupported.push(sequence.unsupported("macOS has no compose key"));
+                continue;
+            }
+            _ => {
+                unsupported.push(sequence.unsupported("sequences must start with a dead key"));
+                continue;
+            }
+        };
+        if rest.contains(&ComposeKey::Multi) {
+            unsupported.push(sequence.unsupported("macOS has no compose key"));
+            continue;
+        }
+
+        let (id, when, terminator) = key_action(first);
+        add_when(&mut actions, id.clone(), when);
+        let mut state = id;
+        terminators.entry(state.clone()).or_insert(terminator.clone());
+        let mut typed = terminator;
+
+        for (i, key) in rest.iter().enumerate() {
+            let (id, when, t) = key_action(key);
+            add_when(&mut actions, id.clone(), when);
+            typed.push_str(&t);
+            if i + 1 == rest.len() {
+                add_when(&mut actions, id, When::Output{state: state.clone(), output: sequence.output.clone()});
+            } else {
+                let next = format!("{} {}", state, id);
+                add_when(&mut actions, id, When::Next{state: state.clone(), next: next.clone()});
+                terminators.entry(next.clone()).or_insert_with(|| typed.clone());
+                state = next;
+            }
+        }
+    }
+
+    let actions = Actions {
+        action: actions.into_iter().map(|(id, when)| Action{id, when}).collect(),
+    };
+    let terminators = Terminators {
+        when: terminators.into_iter().map(|(state, output)| When::Output{state, output}).collect(),
+    };
+    (actions, terminators, unsupported)
+}
+
+/// Turns sequences into KLC dead key tables
+///
+/// Windows has no compose key and dead key tables map one character to one character,
+/// so only sequences starting with a dead key and giving a single character can be expressed.
+/// Longer sequences chain through the output of their first two keys, which then becomes a dead key,
+/// so that output can no longer be typed with those two keys alone.
+#[cfg(feature = "windows")]
+pub fn to_klc(sequences: &[ComposeSequence]) -> (linked_hash_map::LinkedHashMap<char, linked_hash_map::LinkedHashMap<char, crate::klc::DeadKeyResult>>, Vec<Unsupported>) {
+    use crate::klc::DeadKeyResult;
+    use linked_hash_map::LinkedHashMap;
+    use std::collections::BTreeMap;
+
+    let input = |key: &ComposeKey| match *key {
+        ComposeKey::Multi => None,
+        ComposeKey::Dead(_, c) | ComposeKey::Char(c) => Some(c),
+    };
+
+    // Outputs of every sequence by their keys so chains can find their intermediate dead keys
+    let mut outputs = BTreeMap::new();
+    for sequence in sequences {
+        if let Some(c) = sequence.output_char() {
+            outputs.insert(&sequence.keys[..], c);
+        }
+    }
+
+    let mut tables: LinkedHashMap<char, LinkedHashMap<char, DeadKeyResult>> = LinkedHashMap::new();
+    let mut unsupported = Vec::new();
+    // The last step of every sequence
+    let mut finals = Vec::new();
+
+    'sequences: for sequence in sequences {
+        let output = match sequence.output_char() {
+            Some(c) => c,
+            None => {
+                unsupported.push(sequence.unsupported("dead keys can only output a single character"));
+                continue;
+            }
+        };
+        let mut dead = match sequence.keys.first() {
+            Some(&ComposeKey::Dead(_, c)) if sequence.keys.len() > 1 => c,
+            Some(ComposeKey::Multi) => {
+                unsupported.push(sequence.unsupported("Windows has no compose key"));
+                continue;
+            }
+            _ => {
+                unsupported.push(sequence.unsupported("sequences must start with a dead key"));
+                continue;
+            }
+        };
+        let last = sequence.keys.len() - 1;
+
+        // Every prefix of a chain must output a character that can act as the next dead key
+        let mut steps = Vec::with_capacity(last);
+        for i in 1..=last {
+            let c = match input(&sequence.keys[i]) {
+                Some(c) => c,
+                None => {
+                    unsupported.push(sequence.unsupported("Windows has no compose key"));
+                    continue 'sequences;
+                }
+            };
+            let result = if i == last {
+                output
+            } else {
+                match outputs.get(&sequence.keys[..=i]) {
+                    Some(&r) => r,
+                    None => {
+                        unsupported.push(sequence.unsupported("chained dead keys need each prefix to output a character"));
+                        continue 'sequences;
+                    }
+                }
+            };
+            steps.push((dead, c, DeadKeyResult {
+                c: result,
+                dead: i != last,
+            }));
+            dead = result;
+        }
+        let &(last_dead, last_c, _) = steps.last().unwrap();
+        for (dead, c, result) in steps {
+            let table = tables.entry(dead).or_default();
+            let chained = table.get(&c).is_some_and(|r| r.dead);
+            table.insert(c, DeadKeyResult {
+                dead: result.dead || chained,
+                ..result
+            });
+        }
+        finals.push(((last_dead, last_c), sequence));
+    }
+
+    // Outputs that chain on to further keys can't be typed with their own keys alone
+    for ((dead, c), sequence) in finals {
+        if tables.get(&dead).and_then(|t| t.get(&c)).is_some_and(|r| r.dead) {
+            unsupported.push(sequence.unsupported("the output is a dead key chaining to longer sequences"));
+        }
+    }
+
+    (tables, unsupported)
+}
@@ -0,0 +1,209 @@
+//! Conversions between the Windows and Linux formats
+
+use crate::linux::{Character, Compose, DeadKeyMap, Include, Key, Layout, MergeMode, Output, PartialXkbSymbols, Resolver, char_to_name};
+use crate::klc::{Attribute, CapsLockBehaviour, Key as KlcKey, ScanCode, WinKeyLayout};
+
+use std::collections::BTreeMap;
+use std::fmt::{self, Display};
+use std::io::Result;
+
+macro_rules! convert {
+    ($($win_code:expr => $kk:ident, $linux_code:ident;)*) => {
+        /// The XKB key of a scan code, if it has one
+        pub fn try_win_to_linux(k: ScanCode) -> Option<Key> {
+            match k {
+                $( $win_code => Some(Key::$linux_code), )*
+                _ => None,
+            }
+        }
+        /// The scan code of an XKB key, if it has one
+        pub fn linux_to_win(k: &Key) -> Option<ScanCode> {
+            match k {
+                $( Key::$linux_code => Some($win_code), )*
+                Key::Other(_) => None,
+            }
+        }
+    };
+}
+
+scan_codes!(convert);
+
+/// The layout a KLC layout's keys are written against
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Base {
+    /// The symbols file of the country of the KLC locale if there's one, else `us`
+    #[default]
+    Locale,
+    /// An include like `dk(basic)`
+    Include(String),
+    /// No base, every key being written
+    Standalone,
+}
+
+/// Something of a KLC layout that couldn't be converted as it is
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Warning {
+    /// A key with a character for Ctrl, which XKB layouts don't give
+    DroppedCtrl(ScanCode),
+    /// A key with a character for a shift state XKB layouts don't have, e.g. 3 for Shift + Ctrl
+    DroppedState(ScanCode, u8),
+    /// A shift state of a key giving several characters, which a keysym can't
+    DroppedLigature(ScanCode, u8),
+    /// A key with its own characters for caps lock, which XKB layouts don't give
+    DroppedSgCap(ScanCode),
+    /// A scan code without an XKB key
+    UnmappedScanCode(ScanCode),
+    /// An attribute XKB has nothing like
+    UnsupportedAttribute(Attribute),
+    /// A dead key without a dead keysym, left as a character that starts its compose sequences
+    UnmappedDeadKey(char),
+}
+
+impl Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Warning::DroppedCtrl(sc) => write!(f, "the Ctrl level of scan code {:02x} is dropped", sc),
+            Warning::DroppedState(sc, state) => write!(f, "shift state {} of scan code {:02x} is dropped", state, sc),
+            Warning::DroppedLigature(sc, state) => write!(f, "the ligature of shift state {} of scan code {:02x} is dropped", state, sc),
+            Warning::DroppedSgCap(sc) => write!(f, "the caps lock characters of scan code {:02x} are dropped", sc),
+            Warning::UnmappedScanCode(sc) => write!(f, "scan code {:02x} has no XKB key", sc),
+            Warning::UnsupportedAttribute(ref attribute) => write!(f, "attribute {} is not supported", attribute),
+            Warning::UnmappedDeadKey(c) => write!(f, "dead key {:?} has no dead keysym, it is composed instead", c),
+        }
+    }
+}
+
+/// The XKB symbols and compose file of a KLC layout
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Conversion {
+    pub layout: Layout,
+    /// The sequences of the dead keys, which XKB has no tables for
+    pub compose: Compose,
+    pub warnings: Vec<Warning>,
+}
+
+/// Converts KLC layouts to XKB
+#[derive(Debug, Clone, Default)]
+pub struct KlcToXkb {
+    pub base: Base,
+    /// Dead keysyms of dead key characters besides the built-in ones
+    pub dead_keys: DeadKeyMap,
+    /// Where the base is looked for
+    pub resolver: Resolver,
+}
+
+impl KlcToXkb {
+    /// A converter with the system's XKB layouts as bases
+    pub fn new() -> Self {
+        KlcToXkb {
+            resolver: Resolver::from_env(),
+            ..KlcToXkb::default()
+        }
+    }
+    /// The include of the base of a layout, if any
+    pub fn base_include(&self, win_layout: &WinKeyLayout) -> Option<String> {
+        match self.base {
+            Base::Locale => Some(self.resolver.layout_for_locale(&win_layout.locale_name).unwrap_or_else(|| "us".to_owned())),
+            Base::Include(ref include) => Some(include.clone()),
+            Base::Standalone => None,
+        }
+    }
+    fn char_or_dead(&self, c: char, win_layout: &WinKeyLayout) -> Character {
+        match self.dead_keys.get(c) {
+            Some(name) if win_layout.deadkeys.contains_key(&c) => Character::Dead(name.into()),
+            _ => Character::Char(c),
+        }
+    }
+    fn convert_output(&self, win_key: &KlcKey, win_layout: &WinKeyLayout) -> Output {
+        let level = |c: Option<char>| self.char_or_dead(c.unwrap_or('\0'), win_layout);
+        Output {
+            normal: level(win_key.normal),
+            shift: level(win_key.shift),
+            altgr: level(win_key.ctrl_alt),
+            altgr_shift: level(win_key.shift_ctrl_alt),
+        }
+    }
+    /// Converts a layout, keys being written only where they differ from the base
+    pub fn convert(&self, win_layout: &WinKeyLayout) -> Result<Conversion> {
+        let mut warnings = Vec::new();
+        let mut default_partial = PartialXkbSymbols::new("basic".to_owned());
+
+        let base = self.base_include(win_layout);
+        let base_keys = match base {
+            Some(ref base) => {
+                default_partial.includes.push(Include{mode: MergeMode::Default, file: base.clone()});
+                self.resolver.resolve(base)?.into_iter().next().unwrap_or_default().keys
+            }
+            None => BTreeMap::new(),
+        };
+        default_partial.group_mut(1).name = Some(win_layout.name.clone());
+
+        // Attributes become the sections XKB options use for the same
+        for attribute in &win_layout.attributes {
+            let include = match attribute {
+                Attribute::AltGr => "level3(ralt_switch)",
+                Attribute::ShiftLock => "capslock(shiftlock)",
+                _ => {
+                    warnings.push(Warning::UnsupportedAttribute(attribute.clone()));
+                    continue;
+                }
+            };
+            default_partial.includes.push(Include{mode: MergeMode::Override, file: include.to_owned()});
+        }
+
+        for (&scan_code, win_key) in &win_layout.layout {
+            let key_code = match try_win_to_linux(scan_code) {
+                Some(key_code) => key_code,
+                None => {
+                    warnings.push(Warning::UnmappedScanCode(scan_code));
+                    continue;
+                }
+            };
+            // XKB gives control characters for Ctrl by itself
+            if win_key.ctrl.is_some_and(|c| !c.is_control() && Some(c) != win_key.normal) {
+                warnings.push(Warning::DroppedCtrl(scan_code));
+            }
+            for &state in win_key.ligatures.keys() {
+                warnings.push(Warning::DroppedLigature(scan_code, state));
+            }
+            if win_key.cap.contains(CapsLockBehaviour::SG_CAP) {
+                warnings.push(Warning::DroppedSgCap(scan_code));
+            }
+            for &state in win_key.other_states.keys() {
+                warnings.push(Warning::DroppedState(scan_code, state));
+            }
+            let mut output = self.convert_output(win_key, win_layout);
+
+            if let Some(base_out) = base_keys.get(&key_code) {
+                output |= base_out.clone();
+                if base_out == &output {
+                    continue;
+                }
+            }
+
+            default_partial.group_mut(1).keys.insert(key_code, output);
+        }
+
+        // Dead keys left as characters start their sequences
+        let mut compose = Compose::new();
+        for (&c, table) in &win_layout.deadkeys {
+            let first = match self.dead_keys.get(c) {
+                Some(name) => format!("dead_{}", name),
+                None => {
+                    warnings.push(Warning::UnmappedDeadKey(c));
+                    char_to_name(c)
+                }
+            };
+            compose.add_deadkey(&first, table.iter().map(|(&a, b)| (a, b.c)));
+        }
+
+        Ok(Conversion {
+            layout: Layout {
+                default_partial,
+                partials: Vec::new(),
+            },
+            compose,
+            warnings,
+        })
+    }
+}
@@ -0,0 +1,569 @@
+//! Emulation of typing on a layout, turning key events into text
+//!
+//! Every format is turned into a [`Model`] which the [`Emulator`] state machine runs on.
+//! Dead keys are handled the way the platform the model comes from handles them.
+
+use crate::{KlayLayout, KeyboardKey, Level, Out, Special};
+use crate::compose::{ComposeKey, InvalidSequence};
+
+use std::collections::BTreeMap;
+
+/// The platform whose dead key behaviour is emulated
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Platform {
+    /// A dead key followed by a key it doesn't combine with outputs
+    /// the dead key's character followed by the other key's character
+    Windows,
+    /// Compose sequences that don't match are cancelled and output nothing
+    Linux,
+    /// A dead key followed by a key it doesn't combine with outputs the terminator
+    /// of the dead state and then acts on the other key as if no dead key had been pressed
+    MacOs,
+}
+
+/// What pressing a key does
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Action {
+    Text(String),
+    /// Enters the dead state with the given name
+    Dead(String),
+}
+
+impl Action {
+    /// The character that is looked up in a dead key table when this action follows a dead key
+    fn input(&self, model: &Model) -> Option<char> {
+        let s = match self {
+            Action::Text(s) => s,
+            Action::Dead(d) => &model.dead_keys.get(d)?.terminator,
+        };
+        let mut chars = s.chars();
+        let c = chars.next()?;
+        if chars.next().is_none() {
+            Some(c)
+        } else {
+            None
+        }
+    }
+}
+
+/// A dead key state
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeadKey {
+    /// What the next key's character turns into
+    pub compose: BTreeMap<char, Action>,
+    /// Output when the dead state is left without composing
+    pub terminator: String,
+}
+
+/// The actions of a key on every level
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KeyActions {
+    pub levels: BTreeMap<Level, Action>,
+    /// Actions used instead of `levels` while caps lock is on
+    pub caps: BTreeMap<Level, Action>,
+}
+
+impl KeyActions {
+    pub fn get(&self, level: Level, caps_lock: bool) -> Option<&Action> {
+        if caps_lock {
+            if let Some(action) = self.caps.get(&level) {
+                return Some(action);
+            }
+        }
+        self.levels.get(&level)
+    }
+    /// Makes caps lock act like shift on the given levels
+    pub fn caps_shifts(&mut self, normal: Level, shifted: Level) {
+        if let Some(a) = self.levels.get(&shifted) {
+            self.caps.insert(normal, a.clone());
+        }
+        if let Some(a) = self.levels.get(&normal) {
+            self.caps.insert(shifted, a.clone());
+        }
+    }
+}
+
+/// A layout in a form the emulator can run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Model {
+    pub platform: Platform,
+    pub keys: BTreeMap<KeyboardKey, KeyActions>,
+    pub dead_keys: BTreeMap<String, DeadKey>,
+}
+
+impl Model {
+    pub fn new(platform: Platform) -> Self {
+        Model {
+            platform,
+            keys: BTreeMap::new(),
+            dead_keys: BTreeMap::new(),
+        }
+    }
+    /// Makes a model of a Klay layout
+    ///
+    /// Dead keys compose what the sequences of the `[compose]` table starting with them say,
+    /// and an invalid sequence is an error
+    pub fn from_klay(layout: &KlayLayout, platform: Platform) -> Result<Self, InvalidSequence> {
+        let mut model = Model::new(platform);
+
+        for (name, special) in &layout.special {
+            let Special::Deadkey{deadkey} = *special;
+            model.dead_keys.insert(name.to_string(), DeadKey {
+                compose: BTreeMap::new(),
+                terminator: deadkey.to_string(),
+            });
+        }
+
+        for (&key, outs) in &layout.keymap {
+            let mut actions = KeyActions::default();
+            for &level in Level::ALL.iter() {
+                let action = match *outs.get(level) {
+                    Out::Char('\0') => continue,
+                    Out::Char(c) => Action::Text(c.to_string()),
+                    Out::Special(ref s) if model.dead_keys.contains_key(&**s) => Action::Dead(s.to_string()),
+                    Out::Special(_) => continue,
+                };
+                actions.levels.insert(level, action);
+            }
+            model.keys.insert(key, actions);
+        }
+
+        // Sequences starting with a dead key become its tables, there's no compose key to start the others
+        for sequence in layout.compose_sequences()? {
+            if let Some((ComposeKey::Dead(name, _), rest)) = sequence.keys.split_first() {
+                let inputs = rest.iter().map(|k| match *k {
+                    ComposeKey::Multi => None,
+                    ComposeKey::Dead(_, c) | ComposeKey::Char(c) => Some(c),
+                }).collect::<Option<Vec<_>>>();
+                if let Some(inputs) = inputs.filter(|i| !i.is_empty()) {
+                    let terminator = model.dead_keys[&**name].terminator.clone();
+                    model.add_sequence(name, terminator, &inputs, &sequence.output);
+                }
+            }
+        }
+
+        Ok(model)
+    }
+    /// Adds a sequence of characters typed after the dead key `first` to the dead key tables
+    ///
+    /// Intermediate states are named after the keys leading up to them.
+    pub fn add_sequence(&mut self, first: &str, terminator: String, inputs: &[char], output: &str) {
+        let mut state = first.to_owned();
+        self.dead_keys.entry(state.clone()).or_insert_with(|| DeadKey {
+            compose: BTreeMap::new(),
+            terminator,
+        });
+
+        for (i, &c) in inputs.iter().enumerate() {
+            let action = if i + 1 == inputs.len() {
+                Action::Text(output.to_owned())
+            } else {
+                let next = format!("{} {}", state, c);
+                self.dead_keys.entry(next.clone()).or_default();
+                Action::Dead(next)
+            };
+            self.dead_keys.get_mut(&state).unwrap().compose.insert(c, action.clone());
+            if let Action::Dead(next) = action {
+                state = next;
+            }
+        }
+    }
+}
+
+#[cfg(feature = "windows")]
+impl Model {
+    /// Makes a model of a KLC layout
+    pub fn from_klc(layout: &crate::klc::WinKeyLayout) -> Self {
+        use crate::klc::{scan_code_to_key, CapsLockBehaviour};
+
+        let mut model = Model::new(Platform::Windows);
+        let action = |c: Option<char>| match c {
+            None | Some('\0') => None,
+            Some(c) if layout.deadkeys.contains_key(&c) => Some(Action::Dead(c.to_string())),
+            Some(c) => Some(Action::Text(c.to_string())),
+        };
+
+        for (&dead, table) in &layout.deadkeys {
+            // Results with a table of their own chain on to it, as the KLC writer marks them
+            let result = |b: &crate::klc::DeadKeyResult| if b.dead || (b.c != dead && layout.deadkeys.contains_key(&b.c)) {
+                Action::Dead(b.c.to_string())
+            } else {
+                Action::Text(b.c.to_string())
+            };
+            model.dead_keys.insert(dead.to_string(), DeadKey {
+                compose: table.iter().map(|(&a, b)| (a, result(b))).collect(),
+                terminator: dead.to_string(),
+            });
+        }
+
+        for (&scan_code, key) in &layout.layout {
+            let kk = match scan_code_to_key(scan_code) {
+                Some(kk) => kk,
+                None => continue,
+            };
+            let mut actions = KeyActions::default();
+            // The shift states of the levels
+            let levels = [(Level::Normal, 0), (Level::Shift, 1), (Level::AltGr, 6), (Level::AltGrShift, 7)];
+            for &(level, state) in levels.iter() {
+                let a = match key.ligatures.get(&state) {
+                    Some(text) => Some(Action::Text(text.clone())),
+                    None => action(key.get(state)),
+                };
+                if let Some(a) = a {
+                    actions.levels.insert(level, a);
+                }
+            }
+            if key.cap.contains(CapsLockBehaviour::SHIFT_ON_CAPS) {
+                actions.caps_shifts(Level::Normal, Level::Shift);
+            }
+            if key.cap.contains(CapsLockBehaviour::SHIFT_ON_CAPS_ALT) {
+                actions.caps_shifts(Level::AltGr, Level::AltGrShift);
+            }
+            if key.cap.contains(CapsLockBehaviour::SG_CAP) {
+                for &(level, state) in levels.iter() {
+                    if let Some(a) = action(key.sg_caps.get(&state).copied()) {
+                        actions.caps.insert(level, a);
+                    }
+                }
+            }
+            model.keys.insert(kk, actions);
+        }
+
+        model
+    }
+}
+
+#[cfg(feature = "linux")]
+impl Model {
+    /// Makes a model of the keys of an XKB symbols section
+    ///
+    /// Only the first group is used, see [`PartialXkbSymbols::select_group`](crate::linux::PartialXkbSymbols::select_group)
+    /// for the others. Included sections aren't followed, and dead keys only compose what their compose tables say.
+    pub fn from_xkb(symbols: &crate::linux::PartialXkbSymbols) -> std::io::Result<Self> {
+        Self::from_xkb_with_types(symbols, &[])
+    }
+    /// Makes a model of the keys of an XKB symbols section that may use custom key types
+    ///
+    /// Caps lock does what the type of each key says, keys without one getting the type
+    /// the XKB writer would give them. Keys whose type is neither built in nor one of `types` are an error.
+    pub fn from_xkb_with_types(symbols: &crate::linux::PartialXkbSymbols, types: &[crate::linux::KeyType]) -> std::io::Result<Self> {
+        use crate::linux::{Character, dead_to_char, find_type};
+        use std::io::{Error, ErrorKind};
+
+        let mut model = Model::new(Platform::Linux);
+        let group = match symbols.groups.first() {
+            Some(group) => group,
+            None => return Ok(model),
+        };
+
+        for (key, output) in &group.keys {
+            let kk = match key.keyboard_key() {
+                Some(kk) => kk,
+                None => continue,
+            };
+            let mut actions = KeyActions::default();
+            let levels = [
+                (Level::Normal, &output.normal),
+                (Level::Shift, &output.shift),
+                (Level::AltGr, &output.altgr),
+                (Level::AltGrShift, &output.altgr_shift),
+            ];
+            for &(level, c) in levels.iter() {
+                let action = match *c {
+                    Character::Char('\0') | Character::Symbol(_) => continue,
+                    Character::Char(c) => Action::Text(c.to_string()),
+                    Character::Dead(ref name) => {
+                        let name = format!("dead_{}", name);
+                        model.dead_keys.entry(name.clone()).or_insert_with(|| DeadKey {
+                            compose: BTreeMap::new(),
+                            terminator: dead_to_char(&name[5..]).map(|c| c.to_string()).unwrap_or_default(),
+                        });
+                        Action::Dead(name)
+                    }
+                };
+                actions.levels.insert(level, action);
+            }
+            let name = group.key_type(key).unwrap_or_default();
+            let key_type = find_type(name, types).ok_or_else(|| {
+                Error::new(ErrorKind::InvalidData, format!("unknown key type {:?} of key {}", name, key))
+            })?;
+            for &level in Level::ALL.iter() {
+                let mods = Modifiers::from(level);
+                let caps_level = key_type.level_for(mods.shift, true, mods.altgr);
+                if caps_level != key_type.level_for(mods.shift, false, mods.altgr) {
+                    if let Some(action) = Level::ALL.get(caps_level - 1).and_then(|l| actions.levels.get(l)) {
+                        actions.caps.insert(level, action.clone());
+                    }
+                }
+            }
+            model.keys.insert(kk, actions);
+        }
+
+        Ok(model)
+    }
+    /// Adds the compose sequences that start with a dead key as dead key tables
+    pub fn add_compose(&mut self, compose: &crate::linux::Compose) {
+        use crate::linux::{name_to_char, dead_to_char};
+
+        let input = |key: &str| match key.strip_prefix("dead_") {
+            Some(dead) => dead_to_char(dead),
+            None => name_to_char(key),
+        };
+
+        for sequence in &compose.sequences {
+            let (first, rest) = match sequence.keys.split_first() {
+                Some((first, rest)) if first.starts_with("dead_") && !rest.is_empty() => (first, rest),
+                _ => continue,
+            };
+            let inputs = rest.iter().map(|k| input(k)).collect::<Option<Vec<_>>>();
+            if let Some(inputs) = inputs {
+                let terminator = input(first).map(|c| c.to_string()).unwrap_or_default();
+                self.add_sequence(first, terminator, &inputs, &sequence.output);
+            }
+        }
+    }
+}
+
+#[cfg(feature = "macos")]
+impl Model {
+    /// Makes a model of a macOS keylayout using its first key map set
+    pub fn from_keylayout(layout: &crate::keylayout::KeyLayout) -> Self {
+        use crate::keylayout::{Key, When, key_code_to_key};
+
+        let mut model = Model::new(Platform::MacOs);
+
+        for when in &layout.terminators.when {
+            if let When::Output{ref state, ref output} = *when {
+                model.dead_keys.entry(state.clone()).or_default().terminator = output.clone();
+            }
+        }
+
+        let mut actions = BTreeMap::new();
+        for action in &layout.actions.action {
+            let mut base = None;
+            let mut states = Vec::new();
+            for when in &action.when {
+                let (state, a) = match *when {
+                    When::Output{ref state, ref output} => (state, Action::Text(output.clone())),
+                    When::Next{ref state, ref next} => (state, Action::Dead(next.clone())),
+                };
+                if state == "none" {
+                    base = Some(a);
+                } else {
+                    states.push((state.clone(), a));
+                }
+            }
+            if let Some(base) = base {
+                if let Some(c) = base.input(&model) {
+                    for (state, a) in states {
+                        model.dead_keys.entry(state).or_default().compose.insert(c, a);
+                    }
+                }
+                actions.insert(action.id.clone(), base);
+            }
+        }
+
+        let select = &layout.modifier_map;
+        let map_index = |shift, option, caps| {
+            select.key_map_select.iter()
+                .find(|kms| kms.modifier.iter().any(|m| modifier_matches(&m.keys, shift, option, caps)))
+                .map(|kms| kms.map_index)
+                .unwrap_or(select.default_index)
+        };
+
+        let levels = [
+            (Level::Normal, false, false),
+            (Level::Shift, true, false),
+            (Level::AltGr, false, true),
+            (Level::AltGrShift, true, true),
+        ];
+        for &caps in [false, true].iter() {
+            for &(level, shift, option) in levels.iter() {
+                let index = map_index(shift, option, caps);
+                let key_map = match layout.key_map_set.key_map.iter().find(|km| km.index == index) {
+                    Some(km) => km,
+                    None => continue,
+                };
+                for key in &key_map.key {
+                    let (code, action) = match *key {
+                        Key::Output{code, ref output} => (code, Action::Text(output.clone())),
+                        Key::Action{code, ref action} => match actions.get(action) {
+                            Some(a) => (code, a.clone()),
+                            None => continue,
+                        },
+                    };
+                    if let Some(kk) = key_code_to_key(code) {
+                        if action == Action::Text(String::new()) {
+                            continue;
+                        }
+                        let entry = model.keys.entry(kk).or_default();
+                        if !caps {
+                            entry.levels.insert(level, action);
+                        } else if entry.levels.get(&level) != Some(&action) {
+                            entry.caps.insert(level, action);
+                        }
+                    }
+                }
+            }
+        }
+
+        model
+    }
+}
+
+/// Whether a keylayout `modifier` element's keys match the given modifier state
+#[cfg(feature = "macos")]
+fn modifier_matches(keys: &str, shift: bool, option: bool, caps: bool) -> bool {
+    let mut covered = (false, false, false);
+
+    for token in keys.split_whitespace() {
+        let optional = token.ends_with('?');
+        let token = token.trim_end_matches('?');
+        let (active, cover) = match token {
+            "anyShift" | "shift" | "rightShift" => (shift, &mut covered.0),
+            "anyOption" | "option" | "rightOption" => (option, &mut covered.1),
+            "caps" => (caps, &mut covered.2),
+            // Command and control are never held while typing text
+            _ => {
+                if optional {
+                    continue;
+                }
+                return false;
+            }
+        };
+        if !optional && !active {
+            return false;
+        }
+        *cover = true;
+    }
+
+    (!shift || covered.0) && (!option || covered.1) && (!caps || covered.2)
+}
+
+/// The modifiers held when a key is pressed
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub altgr: bool,
+    pub caps_lock: bool,
+}
+
+impl Modifiers {
+    pub fn level(self) -> Level {
+        match (self.altgr, self.shift) {
+            (false, false) => Level::Normal,
+            (false, true) => Level::Shift,
+            (true, false) => Level::AltGr,
+            (true, true) => Level::AltGrShift,
+        }
+    }
+}
+
+impl From<Level> for Modifiers {
+    fn from(level: Level) -> Self {
+        Modifiers {
+            shift: level == Level::Shift || level == Level::AltGrShift,
+            altgr: level == Level::AltGr || level == Level::AltGrShift,
+            caps_lock: false,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum KeyState {
+    Pressed,
+    Released,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct KeyEvent {
+    pub key: KeyboardKey,
+    pub modifiers: Modifiers,
+    pub state: KeyState,
+}
+
+impl KeyEvent {
+    pub fn press(key: KeyboardKey, modifiers: Modifiers) -> Self {
+        KeyEvent {
+            key,
+            modifiers,
+            state: KeyState::Pressed,
+        }
+    }
+}
+
+/// Typing state on a model
+#[derive(Debug, Clone)]
+pub struct Emulator<'a> {
+    model: &'a Model,
+    dead: Option<String>,
+}
+
+impl<'a> Emulator<'a> {
+    pub fn new(model: &'a Model) -> Self {
+        Emulator {
+            model,
+            dead: None,
+        }
+    }
+    /// The dead state waiting for the next key, if any
+    pub fn dead_state(&self) -> Option<&str> {
+        self.dead.as_deref()
+    }
+    pub fn reset(&mut self) {
+        self.dead = None;
+    }
+    /// Handles a key event and returns the text it produces
+    pub fn event(&mut self, event: KeyEvent) -> String {
+        if event.state == KeyState::Released {
+            return String::new();
+        }
+        let action = self.model.keys
+            .get(&event.key)
+            .and_then(|k| k.get(event.modifiers.level(), event.modifiers.caps_lock))
+            .cloned();
+
+        match action {
+            Some(action) => self.act(action),
+            // Keys without anything on them are ignored entirely
+            None => String::new(),
+        }
+    }
+    /// Runs a sequence of key presses and returns all the text produced
+    pub fn type_keys<I: IntoIterator<Item=(KeyboardKey, Modifiers)>>(&mut self, keys: I) -> String {
+        keys.into_iter().map(|(k, m)| self.event(KeyEvent::press(k, m))).collect()
+    }
+    fn terminator(&self, dead: &str) -> String {
+        self.model.dead_keys.get(dead).map(|d| d.terminator.clone()).unwrap_or_default()
+    }
+    fn act(&mut self, action: Action) -> String {
+        let dead = match self.dead.take() {
+            None => match action {
+                Action::Text(s) => return s,
+                Action::Dead(d) => {
+                    self.dead = Some(d);
+                    return String::new();
+                }
+            },
+            Some(dead) => dead,
+        };
+
+        let composed = action.input(self.model).and_then(|c| self.model.dead_keys.get(&dead)?.compose.get(&c));
+        if let Some(composed) = composed {
+            return self.act(composed.clone());
+        }
+
+        match self.model.platform {
+            Platform::Windows => {
+                let mut s = self.terminator(&dead);
+                match action {
+                    Action::Text(t) => s.push_str(&t),
+                    Action::Dead(d) => s.push_str(&self.terminator(&d)),
+                }
+                s
+            }
+            Platform::Linux => String::new(),
+            Platform::MacOs => self.terminator(&dead) + &self.act(action),
+        }
+    }
+}
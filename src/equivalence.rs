@@ -0,0 +1,94 @@
+//! Checking that two layouts behave the same by emulating both of them
+//!
+//! Every key is pressed on every level, with and without caps lock, and every dead
+//! state either layout can reach is followed by every key as well.
+
+use crate::KeyboardKey;
+use crate::emulate::{Model, Modifiers, Emulator};
+
+use std::collections::{BTreeSet, HashSet, VecDeque};
+use std::fmt::{self, Display};
+
+/// A sequence of key presses that produces different text on two layouts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Difference {
+    pub keys: Vec<(KeyboardKey, Modifiers)>,
+    pub left: String,
+    pub right: String,
+}
+
+impl Display for Difference {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (i, &(key, mods)) in self.keys.iter().enumerate() {
+            if i != 0 {
+                write!(f, ", ")?;
+            }
+            write!(f, "{}", key)?;
+            if mods.caps_lock {
+                write!(f, "+caps")?;
+            }
+            if mods.shift {
+                write!(f, "+shift")?;
+            }
+            if mods.altgr {
+                write!(f, "+altgr")?;
+            }
+        }
+        write!(f, ": {:?} vs {:?}", self.left, self.right)
+    }
+}
+
+fn run<'a>(model: &'a Model, keys: &[(KeyboardKey, Modifiers)]) -> (String, Emulator<'a>) {
+    let mut emulator = Emulator::new(model);
+    let mut last = String::new();
+    for &key in keys {
+        last = emulator.type_keys(Some(key));
+    }
+    (last, emulator)
+}
+
+/// Finds every key sequence that behaves differently on the two models
+///
+/// Only the output of the last key of a sequence is compared, since the sequence up to
+/// that point has already been checked on its own.
+pub fn compare(left: &Model, right: &Model) -> Vec<Difference> {
+    let keys: BTreeSet<KeyboardKey> = left.keys.keys().chain(right.keys.keys()).copied().collect();
+    let mut inputs = Vec::with_capacity(keys.len() * 8);
+    for &key in &keys {
+        for &caps_lock in [false, true].iter() {
+            for &(shift, altgr) in [(false, false), (true, false), (false, true), (true, true)].iter() {
+                inputs.push((key, Modifiers{shift, altgr, caps_lock}));
+            }
+        }
+    }
+
+    let mut differences = Vec::new();
+    let mut visited = HashSet::new();
+    let mut queue = VecDeque::new();
+    queue.push_back(Vec::new());
+
+    while let Some(prefix) = queue.pop_front() {
+        for &input in &inputs {
+            let mut keys = prefix.clone();
+            keys.push(input);
+
+            let (left_out, left_em) = run(left, &keys);
+            let (right_out, right_em) = run(right, &keys);
+
+            if left_out != right_out {
+                differences.push(Difference {
+                    keys: keys.clone(),
+                    left: left_out,
+                    right: right_out,
+                });
+            }
+
+            let states = (left_em.dead_state().map(str::to_owned), right_em.dead_state().map(str::to_owned));
+            if states != (None, None) && visited.insert(states) {
+                queue.push_back(keys);
+            }
+        }
+    }
+
+    differences
+}
@@ -1,3 +1,63 @@
+/// Every key with its scan code, generic name and XKB name,
+/// passed to the macros that map between them so they can't disagree
+#[allow(unused_macros)]
+macro_rules! scan_codes {
+    ($then:ident) => {
+        $then! {
+            0x29 => TLD, TLDE;
+            0x02 => E01, AE01;
+            0x03 => E02, AE02;
+            0x04 => E03, AE03;
+            0x05 => E04, AE04;
+            0x06 => E05, AE05;
+            0x07 => E06, AE06;
+            0x08 => E07, AE07;
+            0x09 => E08, AE08;
+            0x0a => E09, AE09;
+            0x0b => E10, AE10;
+            0x0c => E11, AE11;
+            0x0d => E12, AE12;
+            0x10 => D01, AD01;
+            0x11 => D02, AD02;
+            0x12 => D03, AD03;
+            0x13 => D04, AD04;
+            0x14 => D05, AD05;
+            0x15 => D06, AD06;
+            0x16 => D07, AD07;
+            0x17 => D08, AD08;
+            0x18 => D09, AD09;
+            0x19 => D10, AD10;
+            0x1a => D11, AD11;
+            0x1b => D12, AD12;
+            0x1e => C01, AC01;
+            0x1f => C02, AC02;
+            0x20 => C03, AC03;
+            0x21 => C04, AC04;
+            0x22 => C05, AC05;
+            0x23 => C06, AC06;
+            0x24 => C07, AC07;
+            0x25 => C08, AC08;
+            0x26 => C09, AC09;
+            0x27 => C10, AC10;
+            0x28 => C11, AC11;
+            0x2b => BKS, BKSL;
+            0x56 => LGT, LSGT;
+            0x2c => B01, AB01;
+            0x2d => B02, AB02;
+            0x2e => B03, AB03;
+            0x2f => B04, AB04;
+            0x30 => B05, AB05;
+            0x31 => B06, AB06;
+            0x32 => B07, AB07;
+            0x33 => B08, AB08;
+            0x34 => B09, AB09;
+            0x35 => B10, AB10;
+            0x39 => SPC, SPCE;
+            0x53 => KPD, KPDL;
+        }
+    };
+}
+
 #[cfg(feature = "macos")]
 mod macos {
     pub mod keylayout;
@@ -8,6 +68,12 @@ mod windows {
 }
 #[cfg(feature = "linux")]
 pub mod linux;
+pub mod optimize;
+pub mod emulate;
+pub mod equivalence;
+pub mod compose;
+#[cfg(all(feature = "windows", feature = "linux"))]
+pub mod convert;
 
 #[cfg(feature = "macos")]
 pub use macos::keylayout;
@@ -23,12 +89,16 @@ use toml::ser;
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct KlayLayout {
     pub metadata: Metadata,
-    // TODO: This doesn't work for whatver reason
+    #[serde(with = "key_table")]
     pub keymap: BTreeMap<KeyboardKey, Outs>,
     pub special: BTreeMap<Box<str>, Special>,
+    /// Compose sequences like `<Multi_key> <e> <=>` and their output
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub compose: BTreeMap<String, String>,
 }
 
 impl KlayLayout {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Result<Self, toml::de::Error> {
         toml::from_str(s)
     }
@@ -39,6 +109,31 @@ impl KlayLayout {
     }
 }
 
+/// TOML only allows strings as table keys, so maps keyed by enums
+/// go through the names of the variants instead
+pub(crate) mod key_table {
+    use std::collections::BTreeMap;
+    use std::fmt::Display;
+    use serde::{Serialize, Serializer, Deserialize, Deserializer};
+    use serde::de::{Error, IntoDeserializer};
+
+    pub fn serialize<K, V, S>(map: &BTreeMap<K, V>, s: S) -> Result<S::Ok, S::Error>
+    where K: Display, V: Serialize, S: Serializer {
+        s.collect_map(map.iter().map(|(k, v)| (k.to_string(), v)))
+    }
+    pub fn deserialize<'de, K, V, D>(d: D) -> Result<BTreeMap<K, V>, D::Error>
+    where K: Ord + Deserialize<'de>, V: Deserialize<'de>, D: Deserializer<'de> {
+        let map = BTreeMap::<String, V>::deserialize(d)?;
+        map.into_iter()
+            .map(|(k, v)| {
+                let de: serde::de::value::StringDeserializer<D::Error> = k.into_deserializer();
+                K::deserialize(de).map(|k| (k, v))
+            })
+            .collect::<Result<_, _>>()
+            .map_err(D::Error::custom)
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
@@ -100,8 +195,39 @@ pub enum KeyboardKey {
     KPD,
 }
 
+impl Display for KeyboardKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        format!("{:?}", self).to_lowercase().fmt(f)
+    }
+}
+
+/// A shift level of a key
+#[derive(Debug, Copy, Clone, PartialOrd, Ord, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Level {
+    Normal,
+    Shift,
+    AltGr,
+    AltGrShift,
+}
+
+impl Display for Level {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Level::Normal => "normal",
+            Level::Shift => "shift",
+            Level::AltGr => "alt_gr",
+            Level::AltGrShift => "alt_gr_shift",
+        }.fmt(f)
+    }
+}
+
+impl Level {
+    pub const ALL: [Level; 4] = [Level::Normal, Level::Shift, Level::AltGr, Level::AltGrShift];
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
-#[serde(try_from = "Vec<Out>", into = "[Out; 4]")]
+#[serde(try_from = "Vec<Out>", into = "Vec<Out>")]
 pub struct Outs {
     normal: Out,
     shift: Out,
@@ -109,9 +235,39 @@ pub struct Outs {
     altgr_shift: Out,
 }
 
-impl Into<[Out; 4]> for Outs {
-    fn into(self) -> [Out; 4] {
-        let Outs {normal, shift, altgr, altgr_shift} = self;
+impl Outs {
+    pub fn get(&self, level: Level) -> &Out {
+        match level {
+            Level::Normal => &self.normal,
+            Level::Shift => &self.shift,
+            Level::AltGr => &self.altgr,
+            Level::AltGrShift => &self.altgr_shift,
+        }
+    }
+    pub fn get_mut(&mut self, level: Level) -> &mut Out {
+        match level {
+            Level::Normal => &mut self.normal,
+            Level::Shift => &mut self.shift,
+            Level::AltGr => &mut self.altgr,
+            Level::AltGrShift => &mut self.altgr_shift,
+        }
+    }
+}
+
+impl From<Outs> for Vec<Out> {
+    /// Leaves out the unset levels at the end
+    fn from(outs: Outs) -> Vec<Out> {
+        let mut v: Vec<Out> = <[Out; 4]>::from(outs).to_vec();
+        while v.last() == Some(&Out::default()) {
+            v.pop();
+        }
+        v
+    }
+}
+
+impl From<Outs> for [Out; 4] {
+    fn from(outs: Outs) -> [Out; 4] {
+        let Outs {normal, shift, altgr, altgr_shift} = outs;
         [normal, shift, altgr, altgr_shift]
     }
 }
@@ -179,80 +335,3 @@ pub struct Metadata {
     #[serde(default)]
     pub author: String,
 }
-
-#[cfg(all(feature = "windows", feature = "linux"))]
-pub mod convert {
-    use crate::linux::Key;
-    use crate::klc::ScanCode;
-
-    macro_rules! convert {
-        ($win_to_linux:ident, $linux_to_win:ident; $(
-            $win_code:expr => $linux_code:ident,
-        )*) => {
-            pub fn $win_to_linux(k: ScanCode) -> Key {
-                match k {
-                    $( $win_code => Key::$linux_code, )*
-                    b => unimplemented!("{:02x}", b),
-                }
-            }
-            pub fn $linux_to_win(k: Key) -> ScanCode {
-                match k {
-                    $( Key::$linux_code => $win_code, )*
-                }
-            }
-        };
-    }
-
-    convert!{win_to_linux, linux_to_win; 
-        0x02 => AE01,
-        0x03 => AE02,
-        0x04 => AE03,
-        0x05 => AE04,
-        0x06 => AE05,
-        0x07 => AE06,
-        0x08 => AE07,
-        0x09 => AE08,
-        0x0a => AE09,
-        0x0b => AE10,
-        0x0c => AE11,
-        0x0d => AE12,
-        0x10 => AD01,
-        0x11 => AD02,
-        0x12 => AD03,
-        0x13 => AD04,
-        0x14 => AD05,
-        0x15 => AD06,
-        0x16 => AD07,
-        0x17 => AD08,
-        0x18 => AD09,
-        0x19 => AD10,
-        0x1a => AD11,
-        0x1b => AD12,
-        0x1e => AC01,
-        0x1f => AC02,
-        0x20 => AC03,
-        0x21 => AC04,
-        0x22 => AC05,
-        0x23 => AC06,
-        0x24 => AC07,
-        0x25 => AC08,
-        0x26 => AC09,
-        0x27 => AC10,
-        0x28 => AC11,
-        0x29 => TLDE,
-        0x2b => BKSL,
-        0x2c => AB01,
-        0x2d => AB02,
-        0x2e => AB03,
-        0x2f => AB04,
-        0x30 => AB05,
-        0x31 => AB06,
-        0x32 => AB07,
-        0x33 => AB08,
-        0x34 => AB09,
-        0x35 => AB10,
-        0x39 => SPCE,
-        0x56 => LSGT,
-        0x53 => KPDL,
-    }
-}
\ No newline at end of file
@@ -0,0 +1,176 @@
+use super::{char_to_name, name_to_char};
+
+use std::convert::TryFrom;
+use std::io::{Result, Error, ErrorKind, BufReader, BufRead, Read, Write};
+
+/// A sequence of keysyms and the text it composes to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sequence {
+    /// Keysym names, e.g. `dead_acute` or `a`
+    pub keys: Vec<String>,
+    pub output: String,
+}
+
+/// The contents of an XCompose file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Compose {
+    /// Whether the system's default compose file for the locale is included first
+    pub include_default: bool,
+    /// Other files to include
+    pub includes: Vec<String>,
+    pub sequences: Vec<Sequence>,
+}
+
+impl Default for Compose {
+    fn default() -> Self {
+        Compose {
+            include_default: true,
+            includes: Vec::new(),
+            sequences: Vec::new(),
+        }
+    }
+}
+
+fn invalid(line: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("invalid compose line {:?}", line))
+}
+
+/// Reads a quoted string with C-like escapes, returning it and the rest of the line
+///
+/// Hexadecimal and octal escapes are bytes, so a character may be escaped as its UTF-8 bytes.
+fn read_string(s: &str) -> Option<(String, &str)> {
+    let mut out = Vec::new();
+    let mut chars = s.strip_prefix('"')?.char_indices();
+
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((String::from_utf8(out).ok()?, &s[i+2..])),
+            '\\' => {
+                let (_, e) = chars.next()?;
+                match e {
+                    'n' => out.push(b'\n'),
+                    'r' => out.push(b'\r'),
+                    't' => out.push(b'\t'),
+                    'x' | 'X' => {
+                        let hex: String = chars.clone().map(|(_, c)| c).take_while(char::is_ascii_hexdigit).take(2).collect();
+                        for _ in 0..hex.len() {
+                            chars.next();
+                        }
+                        out.push(u8::from_str_radix(&hex, 16).ok()?);
+                    }
+                    '0'..='7' => {
+                        let mut n = e.to_digit(8)?;
+                        for _ in 0..2 {
+                            match chars.clone().next() {
+                                Some((_, d @ '0'..='7')) => {
+                                    n = n * 8 + d.to_digit(8)?;
+                                    chars.next();
+                                }
+                                _ => break,
+                            }
+                        }
+                        out.push(u8::try_from(n).ok()?);
+                    }
+                    e => out.extend_from_slice(e.encode_utf8(&mut [0; 4]).as_bytes()),
+                }
+            }
+            c => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+
+    None
+}
+
+impl Compose {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Adds the compositions of a dead key, `dead` being the keysym name of the dead key
+    pub fn add_deadkey<I: IntoIterator<Item=(char, char)>>(&mut self, dead: &str, table: I) {
+        for (base, composed) in table {
+            self.sequences.push(Sequence {
+                keys: vec![dead.to_owned(), char_to_name(base)],
+                output: composed.to_string(),
+            });
+        }
+    }
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        let mut compose = Compose {
+            include_default: false,
+            .. Compose::default()
+        };
+
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(inc) = line.strip_prefix("include") {
+                let (inc, _) = read_string(inc.trim()).ok_or_else(|| invalid(line))?;
+                if inc == "%L" {
+                    compose.include_default = true;
+                } else {
+                    compose.includes.push(inc);
+                }
+                continue;
+            }
+
+            let colon = line.find(':').ok_or_else(|| invalid(line))?;
+            let keys = line[..colon]
+                .split_whitespace()
+                .map(|k| k.strip_prefix('<').and_then(|k| k.strip_suffix('>')).map(str::to_owned))
+                .collect::<Option<Vec<_>>>()
+                .ok_or_else(|| invalid(line))?;
+            let rest = line[colon+1..].trim();
+            let output = if rest.starts_with('"') {
+                read_string(rest).ok_or_else(|| invalid(line))?.0
+            } else {
+                let keysym = rest.split_whitespace().next().unwrap_or("");
+                name_to_char(keysym).ok_or_else(|| invalid(line))?.to_string()
+            };
+
+            compose.sequences.push(Sequence{keys, output});
+        }
+
+        Ok(compose)
+    }
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        if self.include_default {
+            writeln!(writer, "include \"%L\"")?;
+        }
+        for inc in &self.includes {
+            writeln!(writer, "include \"{}\"", inc)?;
+        }
+        writeln!(writer)?;
+
+        for Sequence{keys, output} in &self.sequences {
+            for key in keys {
+                write!(writer, "<{}> ", key)?;
+            }
+            write!(writer, ": \"")?;
+            for c in output.chars() {
+                match c {
+                    '"' | '\\' => write!(writer, "\\{}", c)?,
+                    '\n' => write!(writer, "\\n")?,
+                    '\r' => write!(writer, "\\r")?,
+                    '\t' => write!(writer, "\\t")?,
+                    // Three digits so that a digit after it isn't read as part of it
+                    c if c.is_control() => for b in c.encode_utf8(&mut [0; 4]).bytes() {
+                        write!(writer, "\\{:03o}", b)?;
+                    },
+                    c => write!(writer, "{}", c)?,
+                }
+            }
+            write!(writer, "\"")?;
+            let mut chars = output.chars();
+            if let (Some(c), None) = (chars.next(), chars.next()) {
+                write!(writer, " {}", char_to_name(c))?;
+            }
+            writeln!(writer)?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,159 @@
+use super::Keysym;
+
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::io::{Result, Error, ErrorKind, BufReader, BufRead, Read};
+
+/// X11 dead keysyms (without the `dead_` prefix) and the spacing character they produce on their own
+pub static DEAD_KEYS: &[(&str, char)] = &[
+    ("grave", '`'),
+    ("acute", '´'),
+    ("circumflex", '^'),
+    ("tilde", '~'),
+    ("perispomeni", '~'),
+    ("macron", '¯'),
+    ("breve", '˘'),
+    ("abovedot", '˙'),
+    ("diaeresis", '¨'),
+    ("abovering", '˚'),
+    ("doubleacute", '˝'),
+    ("caron", 'ˇ'),
+    ("cedilla", '¸'),
+    ("ogonek", '˛'),
+    ("iota", 'ͺ'),
+    ("voiced_sound", '゛'),
+    ("semivoiced_sound", '゜'),
+    ("belowdot", '.'),
+    ("hook", '\u{309}'),
+    ("horn", '\u{31b}'),
+    ("stroke", '/'),
+    ("abovecomma", '\u{313}'),
+    ("psili", '\u{313}'),
+    ("abovereversedcomma", '\u{314}'),
+    ("dasia", '\u{314}'),
+    ("doublegrave", '\u{30f}'),
+    ("belowring", '˳'),
+    ("belowmacron", 'ˍ'),
+    ("belowcircumflex", '\u{32d}'),
+    ("belowtilde", '˷'),
+    ("belowbreve", '\u{32e}'),
+    ("belowdiaeresis", '\u{324}'),
+    ("invertedbreve", '\u{311}'),
+    ("belowcomma", ','),
+    ("currency", '¤'),
+    ("lowline", '_'),
+    ("aboveverticalline", 'ˈ'),
+    ("belowverticalline", 'ˌ'),
+    ("longsolidusoverlay", '\u{338}'),
+    ("greek", 'µ'),
+];
+
+/// Other characters dead keys are known by and their dead keysym: combining characters,
+/// and spacing characters some layouts use instead of the ones X11 does
+pub static DEAD_KEY_ALIASES: &[(char, &str)] = &[
+    ('\u{300}', "grave"),
+    ('\u{301}', "acute"),
+    ('\u{302}', "circumflex"),
+    ('\u{303}', "tilde"),
+    ('\u{304}', "macron"),
+    ('\u{306}', "breve"),
+    ('\u{307}', "abovedot"),
+    ('\u{308}', "diaeresis"),
+    ('\u{30a}', "abovering"),
+    ('\u{30b}', "doubleacute"),
+    ('\u{30c}', "caron"),
+    ('\u{30d}', "aboveverticalline"),
+    ('\u{323}', "belowdot"),
+    ('\u{325}', "belowring"),
+    ('\u{326}', "belowcomma"),
+    ('\u{327}', "cedilla"),
+    ('\u{328}', "ogonek"),
+    ('\u{329}', "belowverticalline"),
+    ('\u{330}', "belowtilde"),
+    ('\u{331}', "belowmacron"),
+    ('\u{332}', "lowline"),
+    ('\u{342}', "perispomeni"),
+    ('\u{345}', "iota"),
+    ('\u{3099}', "voiced_sound"),
+    ('\u{309a}', "semivoiced_sound"),
+    ('\'', "acute"),
+    ('"', "diaeresis"),
+    ('°', "abovering"),
+    ('ˆ', "circumflex"),
+    ('˜', "tilde"),
+    ('ˉ', "macron"),
+    ('ˊ', "acute"),
+    ('ˋ', "grave"),
+    ('΄', "acute"),
+    ('῾', "dasia"),
+    ('᾿', "psili"),
+];
+
+/// The spacing character of a dead keysym, e.g. `acute` gives ´
+pub fn dead_to_char(name: &str) -> Option<char> {
+    DEAD_KEYS.iter().find(|&&(n, _)| n == name).map(|&(_, c)| c)
+}
+
+/// The dead keysym (without the `dead_` prefix) for a spacing character, e.g. ´ gives `acute`
+pub fn char_to_dead(c: char) -> Option<&'static str> {
+    DEAD_KEYS.iter().find(|&&(_, d)| d == c).map(|&(n, _)| n)
+}
+
+/// The dead keysym a dead key giving a character most likely is, by its spacing character,
+/// a combining one or another one layouts use
+pub fn dead_for(c: char) -> Option<&'static str> {
+    char_to_dead(c).or_else(|| DEAD_KEY_ALIASES.iter().find(|&&(a, _)| a == c).map(|&(_, n)| n))
+}
+
+fn invalid(line: &str) -> Error {
+    Error::new(ErrorKind::InvalidData, format!("invalid dead key line {:?}", line))
+}
+
+/// Dead keysyms chosen for characters, which come before the built-in ones
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeadKeyMap {
+    /// Dead keysyms without the `dead_` prefix
+    pub chars: BTreeMap<char, String>,
+}
+
+impl DeadKeyMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Reads lines of a character, or its code point like `U00b4`, and a dead keysym,
+    /// with or without `dead_`, `#` starting comments
+    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
+        let mut map = DeadKeyMap::new();
+        for line in BufReader::new(reader).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut elems = line.split_whitespace();
+            let (c, name) = match (elems.next(), elems.next(), elems.next()) {
+                (Some(c), Some(name), None) => (c, name),
+                _ => return Err(invalid(line)),
+            };
+            let mut chars = c.chars();
+            let c = match (chars.next(), chars.next()) {
+                (Some(c), None) => c,
+                _ => c.strip_prefix('U')
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .and_then(|codepoint| char::try_from(codepoint).ok())
+                    .ok_or_else(|| invalid(line))?,
+            };
+            let name = name.strip_prefix("dead_").unwrap_or(name);
+            if !Keysym::from_name(&format!("dead_{}", name)).is_some_and(Keysym::is_dead) {
+                return Err(invalid(line));
+            }
+            map.chars.insert(c, name.to_owned());
+        }
+        Ok(map)
+    }
+    /// The dead keysym of a character, without `dead_`, this map's own or a built-in one
+    pub fn get(&self, c: char) -> Option<&str> {
+        self.chars.get(&c).map(String::as_str).or_else(|| dead_for(c))
+    }
+}
@@ -0,0 +1,347 @@
+//! Editing symbols files in place, so that what isn't changed keeps its comments and formatting
+
+use super::{Character, Key, KeysymPolicy, Layout, Output, PartialXkbSymbols, SymbolsFile, Section, Statement, Field, Value, Span};
+use super::{group_index, quote};
+
+use std::fmt::{self, Display};
+use std::io::{Result, Error, ErrorKind};
+
+/// A level as it is written, for keysyms and sets of them
+fn written(value: &Value) -> Option<String> {
+    match value {
+        Value::Ident(name) => Some(name.clone()),
+        Value::Set(syms) => {
+            let syms = syms.iter().map(written).collect::<Option<Vec<_>>>()?;
+            Some(format!("{{ {} }}", syms.join(", ")))
+        }
+        _ => None,
+    }
+}
+
+/// The levels of a group as written in a key, without the `NoSymbol`s at the end
+///
+/// Levels that were written as `old` and that the model doesn't change are kept as they were,
+/// sets of keysyms and levels above the fourth included.
+fn levels(output: &Output, old: &[Value], policy: KeysymPolicy) -> String {
+    let chars = [&output.normal, &output.shift, &output.altgr, &output.altgr_shift];
+    let mut levels: Vec<(String, bool)> = chars.iter().enumerate().map(|(i, &c)| {
+        let kept = old.get(i).filter(|v| super::keysym(v).ok().as_ref() == Some(c)).and_then(written);
+        (kept.unwrap_or_else(|| c.keysym(policy)), *c == Character::default())
+    }).collect();
+    levels.extend(old.iter().skip(chars.len()).map(|v| match written(v) {
+        Some(text) => {
+            let empty = text == "NoSymbol";
+            (text, empty)
+        }
+        None => ("NoSymbol".to_owned(), true),
+    }));
+    while levels.len() > 1 && levels.last().is_some_and(|&(_, empty)| empty) {
+        levels.pop();
+    }
+    let levels: Vec<String> = levels.into_iter().map(|(text, _)| text).collect();
+    format!("[ {} ]", levels.join(", "))
+}
+
+/// The fields of a key giving the symbols of a group, with the group
+fn symbol_fields(fields: &[Field]) -> Result<Vec<(usize, &Field)>> {
+    let mut bare = 0;
+    let mut symbols = Vec::new();
+    for field in fields {
+        let group = match field.name {
+            None => {
+                bare += 1;
+                bare
+            }
+            Some(ref name) if name == "symbols" => match field.index {
+                Some(_) => group_index(field.group())?,
+                None => 1,
+            },
+            _ => continue,
+        };
+        symbols.push((group, field));
+    }
+    Ok(symbols)
+}
+
+/// The start of the line something is on, if only whitespace comes before it
+fn line_start(src: &str, at: usize) -> Option<usize> {
+    let start = src[..at].rfind('\n').map_or(0, |i| i + 1);
+    if src[start..at].trim().is_empty() {
+        Some(start)
+    } else {
+        None
+    }
+}
+
+/// The end of the line something is on, after the newline, if only whitespace comes after it
+fn line_end(src: &str, at: usize) -> Option<usize> {
+    let end = src[at..].find('\n').map_or(src.len(), |i| at + i + 1);
+    if src[at..end].trim().is_empty() {
+        Some(end)
+    } else {
+        None
+    }
+}
+
+/// A symbols file that is changed by replacing only the text of what changes
+///
+/// Keys are written like `key <AD01> { [ q, Q ] };`, but keys that don't change
+/// and everything else in the file, including comments, are kept as they are.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolsEditor {
+    src: String,
+    file: SymbolsFile,
+    /// How the characters of keys that are written are named
+    pub keysyms: KeysymPolicy,
+}
+
+impl SymbolsEditor {
+    pub fn new(src: String) -> Result<Self> {
+        let file = SymbolsFile::parse(&src)?;
+        Ok(SymbolsEditor {
+            src,
+            file,
+            keysyms: KeysymPolicy::default(),
+        })
+    }
+    pub fn file(&self) -> &SymbolsFile {
+        &self.file
+    }
+    pub fn as_str(&self) -> &str {
+        &self.src
+    }
+    pub fn into_string(self) -> String {
+        self.src
+    }
+    fn section(&self, name: Option<&str>) -> Result<&Section> {
+        self.file.section(name)
+            .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("section {:?} not found", name.unwrap_or("default"))))
+    }
+    /// Replaces some of the source and parses it again
+    fn splice(&mut self, at: Span, text: &str) -> Result<()> {
+        let mut src = String::with_capacity(self.src.len() + text.len());
+        src.push_str(&self.src[..at.start]);
+        src.push_str(text);
+        src.push_str(&self.src[at.end..]);
+        self.file = SymbolsFile::parse(&src)?;
+        self.src = src;
+        Ok(())
+    }
+    /// Where a new statement goes in a section, after the last one, and the text before it
+    fn insertion_point(&self, section: &Section) -> (usize, String) {
+        match section.spans.last() {
+            Some(last) => {
+                let indent = line_start(&self.src, last.start)
+                    .map_or("    ", |start| &self.src[start..last.start]);
+                // After the line of the last statement, unless the section ends on it
+                let end = self.src[last.end..].find('\n').map_or(self.src.len(), |i| last.end + i + 1);
+                if end <= section.body.end {
+                    (end, indent.to_owned())
+                } else {
+                    (last.end, format!("\n{}", indent))
+                }
+            }
+            None => match line_start(&self.src, section.body.end) {
+                Some(start) => (start, "    ".to_owned()),
+                None => (section.body.end, "\n    ".to_owned()),
+            },
+        }
+    }
+    /// Sets the levels of a key in a group, numbered from 1, of a section or the default one
+    ///
+    /// The last statement for the key is changed, and one is added if there's none.
+    pub fn set_key(&mut self, section: Option<&str>, key: &Key, group: usize, output: &Output) -> Result<()> {
+        let sect = self.section(section)?;
+        let statement = sect.statements.iter().zip(&sect.spans).rev().find_map(|s| match s {
+            (Statement::Key{name, fields, ..}, span) if name == key.name() => Some((fields, span)),
+            _ => None,
+        });
+
+        let (at, text) = match statement {
+            Some((fields, span)) => match symbol_fields(fields)?.into_iter().find(|&(g, _)| g == group) {
+                Some((_, field)) => {
+                    let old = match field.value {
+                        Value::List(ref old) => &old[..],
+                        _ => &[],
+                    };
+                    (field.span.clone(), levels(output, old, self.keysyms))
+                }
+                // The group goes after the last field of the key
+                None => {
+                    let close = self.src[..span.end].rfind('}').unwrap();
+                    let at = self.src[..close].trim_end().len();
+                    let comma = if fields.is_empty() { " " } else { ", " };
+                    (at..at, format!("{}symbols[Group{}]= {}", comma, group, levels(output, &[], self.keysyms)))
+                }
+            },
+            None => {
+                let (at, before) = self.insertion_point(sect);
+                let symbols = if group == 1 {
+                    levels(output, &[], self.keysyms)
+                } else {
+                    format!("symbols[Group{}]= {}", group, levels(output, &[], self.keysyms))
+                };
+                (at..at, format!("{}key <{}> {{ {} }};\n", before, key, symbols))
+            }
+        };
+        self.splice(at, &text)
+    }
+    /// Removes every statement for a key from a section, returns whether there were any
+    pub fn remove_key(&mut self, section: Option<&str>, key: &Key) -> Result<bool> {
+        let mut removed = false;
+        loop {
+            let sect = self.section(section)?;
+            let span = sect.statements.iter().zip(&sect.spans).find_map(|s| match s {
+                (Statement::Key{name, ..}, span) if name == key.name() => Some(span.clone()),
+                _ => None,
+            });
+            let span = match span {
+                Some(span) => span,
+                None => return Ok(removed),
+            };
+            // A key on a line of its own takes the line with it
+            let at = match (line_start(&self.src, span.start), line_end(&self.src, span.end)) {
+                (Some(start), Some(end)) => start..end,
+                _ => span,
+            };
+            self.splice(at, "")?;
+            removed = true;
+        }
+    }
+    /// Sets the name of a group, numbered from 1
+    pub fn set_group_name(&mut self, section: Option<&str>, group: usize, name: &str) -> Result<()> {
+        let sect = self.section(section)?;
+        let text = quote(name);
+        let field = sect.statements.iter().rev().find_map(|s| match s {
+            Statement::Assign(field @ Field{value: Value::Str(_), ..})
+                if field.name.as_deref() == Some("name") && field.group().map(usize::from) == Some(group) => Some(field),
+            _ => None,
+        });
+
+        let (at, text) = match field {
+            Some(field) => (field.span.clone(), text),
+            None => {
+                let (at, before) = self.insertion_point(sect);
+                (at..at, format!("{}name[Group{}]= {};\n", before, group, text))
+            }
+        };
+        self.splice(at, &text)
+    }
+    /// Changes a section so it gives the keys and group names of a model of it
+    ///
+    /// Only keys whose levels differ are written, and keys the model doesn't have are removed.
+    /// Includes and key types are left as they are.
+    pub fn update_section(&mut self, symbols: &PartialXkbSymbols) -> Result<()> {
+        let name = Some(&*symbols.name);
+        let current = PartialXkbSymbols::from_section(self.section(name)?)?;
+
+        for (i, group) in symbols.groups.iter().enumerate() {
+            let old = current.groups.get(i);
+            if let Some(ref group_name) = group.name {
+                if old.and_then(|g| g.name.as_ref()) != Some(group_name) {
+                    self.set_group_name(name, i + 1, group_name)?;
+                }
+            }
+            for (key, output) in &group.keys {
+                if old.and_then(|g| g.keys.get(key)) != Some(output) {
+                    self.set_key(name, key, i + 1, output)?;
+                }
+            }
+        }
+        for key in current.groups.iter().flat_map(|g| g.keys.keys()) {
+            if !symbols.groups.iter().any(|g| g.keys.contains_key(key)) {
+                self.remove_key(name, key)?;
+            }
+        }
+        Ok(())
+    }
+    /// Takes the `default` flag off the default section, if there's one
+    fn clear_default(&mut self) -> Result<()> {
+        let sect = match self.file.sections.iter().find(|s| s.is_default()) {
+            Some(sect) => sect,
+            None => return Ok(()),
+        };
+        let header = &self.src[sect.span.clone()];
+        let header = &header[..header.find(&*sect.kind).unwrap_or(0)];
+        let mut at = None;
+        let mut offset = 0;
+        for word in header.split_whitespace() {
+            let start = offset + header[offset..].find(word).unwrap();
+            offset = start + word.len();
+            if word.eq_ignore_ascii_case("default") {
+                let end = offset + header[offset..].len() - header[offset..].trim_start().len();
+                at = Some(sect.span.start + start..sect.span.start + end);
+                break;
+            }
+        }
+        match at {
+            Some(at) => self.splice(at, ""),
+            None => Ok(()),
+        }
+    }
+    /// Puts a section in place of the one of the same name, or adds it at the end
+    ///
+    /// Unlike [`update_section`](Self::update_section) all of the section is written again.
+    /// If `default` is set it becomes the default section in place of the current one.
+    pub fn replace_section(&mut self, symbols: &PartialXkbSymbols, default: bool) -> Result<()> {
+        if default {
+            self.clear_default()?;
+        }
+        let text = format!("{}{}", if default { "default  " } else { "" }, super::WithKeysyms(symbols, self.keysyms));
+        match self.file.section(Some(&symbols.name)) {
+            Some(sect) => {
+                let at = sect.span.clone();
+                self.splice(at, text.trim_end())
+            }
+            None => {
+                let at = self.src.len();
+                let newline = if self.src.is_empty() || self.src.ends_with("\n\n") { "" } else if self.src.ends_with('\n') { "\n" } else { "\n\n" };
+                self.splice(at..at, &format!("{}{}\n", newline, text))
+            }
+        }
+    }
+    /// Removes a section, returns whether there was one
+    ///
+    /// A section on lines of its own takes them with it, and the blank line that separates it
+    /// from the others.
+    pub fn remove_section(&mut self, name: &str) -> Result<bool> {
+        let span = match self.file.section(Some(name)) {
+            Some(sect) => sect.span.clone(),
+            None => return Ok(false),
+        };
+        let at = match (line_start(&self.src, span.start), line_end(&self.src, span.end)) {
+            (Some(start), Some(end)) => {
+                let blank = start == 0 || self.src[..start].ends_with("\n\n");
+                match line_end(&self.src, end) {
+                    Some(next) if blank && next > end => start..next,
+                    // At the end of the file the blank line before it goes instead
+                    _ if end == self.src.len() && self.src[..start].ends_with("\n\n") => start - 1..end,
+                    _ => start..end,
+                }
+            }
+            _ => span,
+        };
+        self.splice(at, "")?;
+        Ok(true)
+    }
+    /// Changes the file so it gives a layout, sections it doesn't have yet being added at the end
+    pub fn update(&mut self, layout: &Layout) -> Result<()> {
+        let partials = Some(&layout.default_partial).into_iter().chain(&layout.partials);
+        for partial in partials {
+            if self.file.section(Some(&partial.name)).is_some() {
+                self.update_section(partial)?;
+            } else {
+                let at = self.src.len();
+                let newline = if self.src.is_empty() || self.src.ends_with("\n\n") { "" } else if self.src.ends_with('\n') { "\n" } else { "\n\n" };
+                self.splice(at..at, &format!("{}{}\n", newline, super::WithKeysyms(partial, self.keysyms)))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Display for SymbolsEditor {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.src)
+    }
+}
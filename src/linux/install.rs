@@ -0,0 +1,118 @@
+//! Installing layouts into XKB directory trees like `~/.config/xkb`
+
+use super::{Layout, RegistryEntry, SymbolsEditor, user_root, write_registry, add_to_registry, remove_from_registry, registry_variants};
+
+use std::fs::{File, create_dir_all, read_to_string, remove_file, write};
+use std::io::{Result, Error, ErrorKind};
+use std::path::PathBuf;
+
+/// Installs layouts as `symbols/<name>` and registers them in `rules/evdev.xml`
+///
+/// Only `evdev.xml` is written, as that's the registry libxkbcommon reads from user directories.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Installer {
+    pub root: PathBuf,
+}
+
+/// Checks that a layout name is a file name and nothing more
+fn check_name(name: &str) -> Result<()> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(&['/', '\\', '\0'][..]) {
+        return Err(Error::new(ErrorKind::InvalidInput, format!("invalid layout name {:?}", name)));
+    }
+    Ok(())
+}
+
+fn empty_registry() -> String {
+    let mut xml = Vec::new();
+    write_registry(&mut xml, &[]).unwrap();
+    String::from_utf8(xml).unwrap()
+}
+
+impl Installer {
+    pub fn new(root: PathBuf) -> Self {
+        Installer {
+            root,
+        }
+    }
+    /// Installs into the XKB directory of the user, see [`user_root`]
+    pub fn user() -> Result<Self> {
+        user_root().map(Self::new).ok_or_else(|| Error::new(ErrorKind::NotFound, "no home directory"))
+    }
+    /// The path of the symbols file `name`, which must not be a path itself
+    pub fn symbols_path(&self, name: &str) -> Result<PathBuf> {
+        check_name(name)?;
+        Ok(self.root.join("symbols").join(name))
+    }
+    pub fn registry_path(&self) -> PathBuf {
+        self.root.join("rules").join("evdev.xml")
+    }
+    /// Installs a layout as the symbols file `name`
+    ///
+    /// Sections already installed under that name are kept as they are written unless the layout
+    /// has them, the default section of the layout becoming the default one.
+    /// Other entries of the registry are left alone.
+    pub fn install(&self, name: &str, layout: &Layout, entry: &RegistryEntry) -> Result<()> {
+        let symbols = self.symbols_path(name)?;
+        create_dir_all(symbols.parent().unwrap())?;
+        if symbols.is_file() {
+            let mut editor = SymbolsEditor::new(read_to_string(&symbols)?)?;
+            editor.replace_section(&layout.default_partial, true)?;
+            for partial in &layout.partials {
+                editor.replace_section(partial, false)?;
+            }
+            write(&symbols, editor.into_string())?;
+        } else {
+            layout.write(File::create(&symbols)?)?;
+        }
+
+        let registry = self.registry_path();
+        create_dir_all(registry.parent().unwrap())?;
+        let xml = if registry.is_file() {
+            read_to_string(&registry)?
+        } else {
+            empty_registry()
+        };
+        write(&registry, add_to_registry(&xml, entry)?)
+    }
+    /// Removes a layout and its registry entry, returns whether it was installed
+    ///
+    /// Only the sections the entry names are removed, the default one and the variants, and the
+    /// symbols file goes only once no section is left in it. Without an entry it's left alone.
+    /// The registry is removed as well when nothing else is left in it.
+    pub fn uninstall(&self, name: &str) -> Result<bool> {
+        let mut found = false;
+        let symbols = self.symbols_path(name)?;
+        let registry = self.registry_path();
+        let variants = if registry.is_file() {
+            registry_variants(&read_to_string(&registry)?, name)
+        } else {
+            None
+        };
+        if let (Some(variants), true) = (variants, symbols.is_file()) {
+            let mut editor = SymbolsEditor::new(read_to_string(&symbols)?)?;
+            let default = editor.file().section(None).map(|s| s.name.clone());
+            for section in default.into_iter().chain(variants) {
+                editor.remove_section(&section)?;
+            }
+            if editor.file().sections.is_empty() {
+                remove_file(&symbols)?;
+            } else {
+                write(&symbols, editor.into_string())?;
+            }
+            found = true;
+        }
+
+        let registry = self.registry_path();
+        if registry.is_file() {
+            if let Some(xml) = remove_from_registry(&read_to_string(&registry)?, name) {
+                if xml == empty_registry() {
+                    remove_file(&registry)?;
+                } else {
+                    write(&registry, xml)?;
+                }
+                found = true;
+            }
+        }
+        Ok(found)
+    }
+}
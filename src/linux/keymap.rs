@@ -0,0 +1,252 @@
+//! Complete `xkb_keymap` files, which need no XKB data to be installed
+
+use super::{Character, Group, Key, KeyType, KeysymPolicy, Output, Layout, Resolver, builtin_types, quote};
+
+use lazy_static::lazy_static;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::io::{Result, Error, ErrorKind, Write};
+
+const KEYCODES: &str = include_str!("keycodes.xkb");
+const COMPAT: &str = include_str!("compat.xkb");
+
+lazy_static! {
+    /// The names of the keys and aliases of the keycodes section
+    static ref KEY_NAMES: BTreeSet<&'static str> = KEYCODES.lines()
+        .map(|l| l.trim().trim_start_matches("alias").trim_start())
+        .filter_map(|l| l.strip_prefix('<')?.split('>').next())
+        .collect();
+}
+
+/// Keys a keymap can't do without, given to the first group if no group has them
+const BASE_KEYS: &[(&str, &[&str])] = &[
+    ("ESC", &["Escape"]),
+    ("BKSP", &["BackSpace"]),
+    ("TAB", &["Tab", "ISO_Left_Tab"]),
+    ("RTRN", &["Return"]),
+    ("CAPS", &["Caps_Lock"]),
+    ("LFSH", &["Shift_L"]),
+    ("RTSH", &["Shift_R"]),
+    ("LCTL", &["Control_L"]),
+    ("RCTL", &["Control_R"]),
+    ("LALT", &["Alt_L"]),
+    ("LWIN", &["Super_L"]),
+    ("RWIN", &["Super_R"]),
+    ("SPCE", &["space"]),
+];
+
+/// The real modifier of modifier keysyms, as in the `pc` symbols of xkeyboard-config
+const MODIFIERS: &[(&str, &str)] = &[
+    ("Shift_L", "Shift"),
+    ("Shift_R", "Shift"),
+    ("Caps_Lock", "Lock"),
+    ("Control_L", "Control"),
+    ("Control_R", "Control"),
+    ("Alt_L", "Mod1"),
+    ("Alt_R", "Mod1"),
+    ("Meta_L", "Mod1"),
+    ("Meta_R", "Mod1"),
+    ("Num_Lock", "Mod2"),
+    ("Super_L", "Mod4"),
+    ("Super_R", "Mod4"),
+    ("Hyper_L", "Mod4"),
+    ("Hyper_R", "Mod4"),
+    ("ISO_Level3_Shift", "Mod5"),
+    ("Mode_switch", "Mod5"),
+];
+
+const REAL_MODIFIERS: &[&str] = &["Shift", "Lock", "Control", "Mod1", "Mod2", "Mod3", "Mod4", "Mod5"];
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+fn levels(output: &Output) -> Vec<&Character> {
+    let mut levels = vec![&output.normal, &output.shift, &output.altgr, &output.altgr_shift];
+    while levels.len() > 1 && levels.last().unwrap().is_zero() {
+        levels.pop();
+    }
+    levels
+}
+
+/// A keymap with every include followed, written with its keycodes, types and compat
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Keymap {
+    pub name: String,
+    pub groups: Vec<Group>,
+    /// Types used by keys besides the built-in ones
+    pub types: Vec<KeyType>,
+    pub keysyms: KeysymPolicy,
+}
+
+impl Keymap {
+    /// Makes a keymap of groups, adding the basic modifier keys that are missing
+    ///
+    /// Right Alt becomes AltGr if any key has a third or fourth level.
+    pub fn new(name: String, mut groups: Vec<Group>) -> Self {
+        if groups.is_empty() {
+            groups.push(Group::default());
+        }
+        let has = |groups: &[Group], key: &Key| groups.iter().any(|g| g.keys.contains_key(key));
+        let altgr = groups.iter().flat_map(|g| g.keys.values()).any(|o| levels(o).len() > 2);
+
+        let ralt: &[&str] = if altgr { &["ISO_Level3_Shift"] } else { &["Alt_R"] };
+        for &(key, syms) in BASE_KEYS.iter().chain(Some(&("RALT", ralt))) {
+            let key = Key::from_name(key);
+            if has(&groups, &key) {
+                continue;
+            }
+            let mut syms = syms.iter().map(|&s| Character::Symbol(s.into()));
+            let output = Output {
+                normal: syms.next().unwrap_or_default(),
+                shift: syms.next().unwrap_or_default(),
+                altgr: Character::default(),
+                altgr_shift: Character::default(),
+            };
+            groups[0].keys.insert(key, output);
+        }
+
+        Keymap {
+            name,
+            groups,
+            types: Vec::new(),
+            keysyms: KeysymPolicy::default(),
+        }
+    }
+    fn find_type(&self, name: &str) -> Option<&KeyType> {
+        super::find_type(name, &self.types)
+    }
+    /// The type a key gets in a group, keys with one level getting `ONE_LEVEL`
+    fn key_type<'a>(group: &'a Group, key: &Key, output: &Output) -> &'a str {
+        match group.types.get(key) {
+            Some(key_type) => key_type,
+            None if levels(output).len() == 1 => "ONE_LEVEL",
+            None => group.key_type(key).unwrap(),
+        }
+    }
+    /// Writes the keymap with the evdev keycodes, the built-in and custom types and the compat
+    /// section klay uses for every keymap
+    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
+        // Like XKB, keys without an evdev keycode are left out
+        let keys: BTreeSet<&Key> = self.groups.iter()
+            .flat_map(|g| g.keys.keys())
+            .filter(|k| KEY_NAMES.contains(k.name()))
+            .collect();
+
+        // Built-in types are left out when a custom type has their name
+        let mut types: Vec<&KeyType> = self.types.iter().collect();
+        types.extend(builtin_types().iter().filter(|t| self.types.iter().all(|c| c.name != t.name)));
+        for group in &self.groups {
+            for (key, output) in group.keys.iter().filter(|(k, _)| keys.contains(k)) {
+                let key_type = Self::key_type(group, key, output);
+                if self.find_type(key_type).is_none() {
+                    return Err(invalid(format!("unknown type {:?} for <{}>", key_type, key)));
+                }
+            }
+        }
+        let virtual_modifiers: BTreeSet<&str> = types.iter()
+            .flat_map(|t| &t.modifiers)
+            .map(String::as_str)
+            .filter(|m| !REAL_MODIFIERS.iter().any(|r| r.eq_ignore_ascii_case(m)))
+            .collect();
+
+        writeln!(writer, "xkb_keymap {{")?;
+        writeln!(writer, "{}", KEYCODES.trim_end())?;
+        writeln!(writer)?;
+
+        writeln!(writer, "xkb_types \"klay\" {{\n")?;
+        if !virtual_modifiers.is_empty() {
+            writeln!(writer, "    virtual_modifiers {};\n", virtual_modifiers.into_iter().collect::<Vec<_>>().join(", "))?;
+        }
+        for key_type in types {
+            writeln!(writer, "{}\n", key_type)?;
+        }
+        writeln!(writer, "}};\n")?;
+
+        writeln!(writer, "{}", COMPAT.trim_end())?;
+        writeln!(writer)?;
+
+        self.write_symbols(&mut writer, &keys)?;
+        writeln!(writer, "}};")
+    }
+    fn write_symbols<W: Write>(&self, writer: &mut W, keys: &BTreeSet<&Key>) -> Result<()> {
+        writeln!(writer, "xkb_symbols {} {{\n", quote(&self.name))?;
+
+        for (i, group) in self.groups.iter().enumerate() {
+            if let Some(ref name) = group.name {
+                writeln!(writer, "    name[Group{}]={};", i + 1, quote(name))?;
+            }
+        }
+        if self.groups.iter().any(|g| g.name.is_some()) {
+            writeln!(writer)?;
+        }
+
+        // Every group and type is written out so nothing is left to XKB to guess
+        let mut modifier_map: BTreeMap<usize, Vec<&Key>> = BTreeMap::new();
+        for &key in keys {
+            write!(writer, "    key <{}>\t{{ ", key)?;
+            let mut first = true;
+            for (i, group) in self.groups.iter().enumerate() {
+                let output = match group.keys.get(key) {
+                    Some(output) => output,
+                    None => continue,
+                };
+                if !first {
+                    write!(writer, ", ")?;
+                }
+                first = false;
+
+                let levels: Vec<String> = levels(output).iter().map(|c| c.keysym(self.keysyms)).collect();
+                write!(writer, "type[Group{0}]= \"{1}\", symbols[Group{0}]= [ {2} ]", i + 1, Self::key_type(group, key, output), levels.join(", "))?;
+
+                let modifier = MODIFIERS.iter()
+                    .find(|&&(sym, _)| matches!(output.normal, Character::Symbol(ref s) if &**s == sym))
+                    .and_then(|&(_, m)| REAL_MODIFIERS.iter().position(|&r| r == m));
+                if let Some(modifier) = modifier {
+                    let keys = modifier_map.entry(modifier).or_default();
+                    if !keys.contains(&key) {
+                        keys.push(key);
+                    }
+                }
+            }
+            writeln!(writer, " }};")?;
+        }
+
+        if !modifier_map.is_empty() {
+            writeln!(writer)?;
+        }
+        for (modifier, keys) in modifier_map {
+            let keys: Vec<String> = keys.iter().map(|k| format!("<{}>", k)).collect();
+            writeln!(writer, "    modifier_map {} {{ {} }};", REAL_MODIFIERS[modifier], keys.join(", "))?;
+        }
+        writeln!(writer, "}};")
+    }
+}
+
+impl Layout {
+    /// The keymap of a section, the default one if none is given, with its includes followed
+    ///
+    /// Includes of other sections of the layout need the layout to be added to the resolver.
+    pub fn keymap(&self, section: Option<&str>, resolver: &Resolver) -> Result<Keymap> {
+        let partial = match section {
+            Some(name) => self.get_partial(name)
+                .ok_or_else(|| Error::new(ErrorKind::NotFound, format!("section {:?} not found", name)))?,
+            None => &self.default_partial,
+        };
+        let mut keymap = Keymap::new(partial.name.clone(), resolver.flatten(partial)?);
+
+        // Types that aren't built in come from the XKB trees of the resolver
+        let used: BTreeSet<&str> = keymap.groups.iter().flat_map(|g| g.types.values()).map(String::as_str).collect();
+        if used.iter().any(|t| super::find_type(t, &[]).is_none()) {
+            let mut types = resolver.types()?;
+            let mut found = BTreeSet::new();
+            types.retain(|t| used.contains(&*t.name) && super::find_type(&t.name, &[]).is_none() && found.insert(t.name.clone()));
+            keymap.types = types;
+        }
+        Ok(keymap)
+    }
+    /// Writes a complete `xkb_keymap` of a section for use without any XKB data installed
+    pub fn write_keymap<W: Write>(&self, writer: W, section: Option<&str>, resolver: &Resolver) -> Result<()> {
+        self.keymap(section, resolver)?.write(writer)
+    }
+}
@@ -0,0 +1,86 @@
+//! Keysyms, the numbers X11 and XKB give to what keys produce
+
+use super::NAMES;
+
+use std::convert::TryFrom;
+use std::fmt::{self, Display};
+
+/// Keysyms of Unicode characters without a keysym of their own are this plus the code point
+const UNICODE_OFFSET: u32 = 0x0100_0000;
+
+/// A number of digits only, unlike `from_str_radix` which takes a sign
+fn parse_number(digits: &str, radix: u32) -> Option<u32> {
+    if digits.is_empty() || !digits.chars().all(|c| c.is_digit(radix)) {
+        return None;
+    }
+    u32::from_str_radix(digits, radix).ok()
+}
+
+/// A keysym by its value, named or not, standing for a character or not
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Keysym(pub u32);
+
+impl Keysym {
+    pub const NO_SYMBOL: Keysym = Keysym(0);
+
+    /// The keysym of a name of keysymdef.h or XF86keysym.h, a `Uxxxx` name or a value,
+    /// in hexadecimal like `0x10020ac` or decimal
+    ///
+    /// `Uxxxx` names are read like XKB does: they are Latin-1 keysyms below `U0100`,
+    /// and control characters aren't keysyms.
+    pub fn from_name(name: &str) -> Option<Self> {
+        if let Some(keysym) = NAMES.get_keysym(name) {
+            return Some(Keysym(keysym));
+        }
+        if let Some(hex) = name.strip_prefix('U') {
+            let codepoint = parse_number(hex, 16)?;
+            match codepoint {
+                0..=0x1f | 0x7f..=0x9f | 0x11_0000.. => None,
+                0x20..=0xff => Some(Keysym(codepoint)),
+                _ => Some(Keysym(UNICODE_OFFSET + codepoint)),
+            }
+        } else if let Some(hex) = name.strip_prefix("0x") {
+            parse_number(hex, 16).map(Keysym)
+        } else {
+            parse_number(name, 10).map(Keysym)
+        }
+    }
+    /// The keysym of a character, its named one if it has one
+    pub fn from_char(c: char) -> Self {
+        match NAMES.get_name(c).and_then(|name| NAMES.get_keysym(name)) {
+            Some(keysym) => Keysym(keysym),
+            None => Keysym(UNICODE_OFFSET + c as u32),
+        }
+    }
+    #[inline]
+    pub fn value(self) -> u32 {
+        self.0
+    }
+    /// The first name of the keysym, if it has one
+    pub fn name(self) -> Option<&'static str> {
+        NAMES.get_keysym_name(self.0)
+    }
+    /// The character the keysym stands for, if any
+    pub fn to_char(self) -> Option<char> {
+        NAMES.get_keysym_char(self.0).or_else(|| match self.0 {
+            // Below 0x100 the values are those of Latin-1, named in the table
+            0x0100_0100..=0x0110_ffff => char::try_from(self.0 - UNICODE_OFFSET).ok(),
+            _ => None,
+        })
+    }
+    #[inline]
+    pub fn is_dead(self) -> bool {
+        self.name().is_some_and(|name| name.starts_with("dead_"))
+    }
+}
+
+/// The name of the keysym, `Uxxxx` for unnamed characters and the value for the rest
+impl Display for Keysym {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match (self.name(), self.to_char()) {
+            (Some(name), _) => f.write_str(name),
+            (None, Some(c)) => write!(f, "U{:04x}", c as u32),
+            (None, None) => write!(f, "{:#x}", self.0),
+        }
+    }
+}
@@ -1,50 +1,113 @@
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::fmt::{self, Display};
 use std::ops::{BitOr, BitOrAssign};
-use std::convert::TryFrom;
+
+use crate::KeyboardKey;
 
 mod names;
 pub use names::NAMES;
+mod keysym;
+pub use keysym::Keysym;
+mod dead;
+pub use dead::{DEAD_KEYS, DEAD_KEY_ALIASES, DeadKeyMap, dead_to_char, char_to_dead, dead_for};
+mod compose;
+pub use compose::{Compose, Sequence};
+mod syntax;
+pub use syntax::{SymbolsFile, Section, Statement, Field, Value, MergeMode, Span, parse_group};
+mod resolve;
+pub use resolve::{Resolver, IncludeSegment, parse_include, user_root};
+mod types;
+pub use types::{KeyType, builtin_types, find_type, automatic_type, suggested_type};
+mod keymap;
+pub use keymap::Keymap;
+mod registry;
+pub use registry::{RegistryEntry, Variant, locale_codes, write_registry, add_to_registry, remove_from_registry, registry_variants};
+mod install;
+pub use install::Installer;
+mod edit;
+pub use edit::SymbolsEditor;
 
 pub fn char_to_name(c: char) -> String {
-    if let Some(name) = NAMES.get_name(c) {
-        name.to_owned()
-    } else {
-        format!("U{:04x}", c as u32)
+    char_to_keysym(c, KeysymPolicy::Canonical)
+}
+
+/// Which keysym names characters are written as
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum KeysymPolicy {
+    /// The first name keysymdef.h gives the character, or `Uxxxx` if it has none
+    #[default]
+    Canonical,
+    /// Always `Uxxxx`, even where there's a name
+    Unicode,
+    /// The first name that isn't deprecated or legacy, or `Uxxxx` if there's none
+    NoDeprecated,
+}
+impl KeysymPolicy {
+    /// The policy of a name like `no-deprecated`, as given on the command line
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "canonical" => KeysymPolicy::Canonical,
+            "unicode" => KeysymPolicy::Unicode,
+            "no-deprecated" => KeysymPolicy::NoDeprecated,
+            _ => return None,
+        })
     }
 }
 
-pub fn name_to_char(name: &str) -> Option<char> {
-    if let Some(c) = NAMES.get_char(name) {
-        Some(c)
-    } else if name.starts_with(name) {
-        <char>::try_from(<u32>::from_str_radix(&name[1..], 16).ok()?).ok()
-    } else {
-        None
+/// The keysym name of a character according to a policy, `NoSymbol` for no character
+pub fn char_to_keysym(c: char, policy: KeysymPolicy) -> String {
+    let name = match policy {
+        _ if c == '\0' => NAMES.get_name(c),
+        KeysymPolicy::Canonical => NAMES.get_name(c),
+        KeysymPolicy::Unicode => None,
+        KeysymPolicy::NoDeprecated => NAMES.get_current_name(c),
+    };
+    match name {
+        Some(name) => name.to_owned(),
+        None => format!("U{:04x}", c as u32),
     }
 }
 
+/// The character of a keysym name, a `Uxxxx` name or a keysym value like `0x10020ac`
+///
+/// Keysyms that aren't characters, like `BackSpace`, and names that aren't keysyms give `None`.
+pub fn name_to_char(name: &str) -> Option<char> {
+    NAMES.get_char(name).or_else(|| Keysym::from_name(name)?.to_char())
+}
+
 macro_rules! key {
     ($key:ident; $(
         $code:ident,
     )*) => {
-        #[repr(u8)]
-        #[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Copy, Clone)]
+        #[derive(Debug, PartialEq, PartialOrd, Eq, Ord, Clone)]
         pub enum $key {
             $(
-                $code
-            ),*
+                $code,
+            )*
+            /// Any other keycode, e.g. `ESC`
+            Other(Box<str>),
         }
 
         impl $key {
-            fn from_str(s: &str) -> Option<Self> {
-                use self::$key::*;
+            pub fn from_name(s: &str) -> Self {
                 match s {
-                    $(stringify!($code) => Some($code),)*
-                    _ => None,
+                    $(stringify!($code) => $key::$code,)*
+                    s => $key::Other(s.into()),
+                }
+            }
+            pub fn name(&self) -> &str {
+                match self {
+                    $($key::$code => stringify!($code),)*
+                    $key::Other(s) => s,
                 }
             }
         }
+
+        impl Display for $key {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                self.name().fmt(f)
+            }
+        }
     };
 }
 
@@ -102,10 +165,84 @@ key! {Key;
     LSGT,
 }
 
+macro_rules! keyboard_key {
+    ($($code:ident => $kk:ident,)*) => {
+        impl Key {
+            /// The generic key at the same position
+            pub fn keyboard_key(&self) -> Option<KeyboardKey> {
+                match self {
+                    $( Key::$code => Some(KeyboardKey::$kk), )*
+                    Key::Other(_) => None,
+                }
+            }
+            pub fn from_keyboard_key(k: KeyboardKey) -> Self {
+                match k {
+                    $( KeyboardKey::$kk => Key::$code, )*
+                }
+            }
+        }
+    };
+}
+
+keyboard_key! {
+    TLDE => TLD,
+    AE01 => E01,
+    AE02 => E02,
+    AE03 => E03,
+    AE04 => E04,
+    AE05 => E05,
+    AE06 => E06,
+    AE07 => E07,
+    AE08 => E08,
+    AE09 => E09,
+    AE10 => E10,
+    AE11 => E11,
+    AE12 => E12,
+    AD01 => D01,
+    AD02 => D02,
+    AD03 => D03,
+    AD04 => D04,
+    AD05 => D05,
+    AD06 => D06,
+    AD07 => D07,
+    AD08 => D08,
+    AD09 => D09,
+    AD10 => D10,
+    AD11 => D11,
+    AD12 => D12,
+    AC01 => C01,
+    AC02 => C02,
+    AC03 => C03,
+    AC04 => C04,
+    AC05 => C05,
+    AC06 => C06,
+    AC07 => C07,
+    AC08 => C08,
+    AC09 => C09,
+    AC10 => C10,
+    AC11 => C11,
+    BKSL => BKS,
+    LSGT => LGT,
+    AB01 => B01,
+    AB02 => B02,
+    AB03 => B03,
+    AB04 => B04,
+    AB05 => B05,
+    AB06 => B06,
+    AB07 => B07,
+    AB08 => B08,
+    AB09 => B09,
+    AB10 => B10,
+    SPCE => SPC,
+    KPDL => KPD,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum Character {
     Char(char),
     Dead(Box<str>),
+    /// A keysym that isn't a character, e.g. `BackSpace`, or one with no name by its value
+    Symbol(Box<str>),
 }
 impl Default for Character {
     fn default() -> Self {
@@ -114,13 +251,7 @@ impl Default for Character {
 }
 impl Display for Character {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            &Self::Char(c) => char_to_name(c).fmt(f),
-            Self::Dead(s) => {
-                let s = format!("dead_{}", s);
-                s.fmt(f)
-            },
-        }
+        self.keysym(KeysymPolicy::default()).fmt(f)
     }
 }
 impl BitOr for Character {
@@ -142,12 +273,43 @@ impl BitOrAssign for Character {
     }
 }
 impl Character {
+    /// The keysym name of the character, characters being named according to a policy
+    pub fn keysym(&self, policy: KeysymPolicy) -> String {
+        match self {
+            &Self::Char(c) => char_to_keysym(c, policy),
+            Self::Dead(s) => format!("dead_{}", s),
+            Self::Symbol(s) => s.to_string(),
+        }
+    }
+    /// The character of a keysym name
+    pub fn from_name(name: &str) -> Self {
+        if let Some(dead) = name.strip_prefix("dead_") {
+            Character::Dead(dead.into())
+        } else if let Some(c) = name_to_char(name) {
+            Character::Char(c)
+        } else {
+            Character::Symbol(name.into())
+        }
+    }
+    /// The keysym of the character, if its name is known
+    pub fn to_keysym(&self) -> Option<Keysym> {
+        match self {
+            &Self::Char(c) => Some(Keysym::from_char(c)),
+            Self::Dead(s) => Keysym::from_name(&format!("dead_{}", s)),
+            Self::Symbol(s) => Keysym::from_name(s),
+        }
+    }
     #[inline]
     fn is_zero(&self) -> bool {
-        if let Character::Char('\0') = *self {
-            true
-        } else {
-            false
+        matches!(*self, Character::Char('\0'))
+    }
+}
+impl From<Keysym> for Character {
+    fn from(keysym: Keysym) -> Self {
+        match (keysym.name(), keysym.to_char()) {
+            (Some(name), _) if keysym.is_dead() => Character::Dead(name["dead_".len()..].into()),
+            (_, Some(c)) => Character::Char(c),
+            _ => Character::Symbol(keysym.to_string().into()),
         }
     }
 }
@@ -179,36 +341,147 @@ impl BitOrAssign for Output {
 }
 impl Display for Output {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let Output{ref normal, ref shift, ref altgr, ref altgr_shift} = self;
+        WithKeysyms(self, KeysymPolicy::default()).fmt(f)
+    }
+}
+/// Something written with characters named according to a policy
+struct WithKeysyms<'a, T>(&'a T, KeysymPolicy);
 
-        write!(f, "{:>10}, {:>10}", normal, shift)?;
-        if !(altgr.is_zero() || altgr_shift.is_zero()) {
-            write!(f, ", {:>12}, {:>12}", self.altgr, self.altgr_shift)?;
+impl Display for WithKeysyms<'_, Output> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let WithKeysyms(Output{normal, shift, altgr, altgr_shift}, policy) = *self;
+
+        write!(f, "{:>10}, {:>10}", normal.keysym(policy), shift.keysym(policy))?;
+        if !(altgr.is_zero() && altgr_shift.is_zero()) {
+            write!(f, ", {:>12}, {:>12}", altgr.keysym(policy), altgr_shift.keysym(policy))?;
         }
         Ok(())
     }
 }
-#[derive(Debug, Clone)]
+/// An include statement like `include "pc+us(intl)"` or `augment "level3(ralt_switch)"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Include {
+    pub mode: MergeMode,
+    pub file: String,
+}
+/// The most groups a key can have
+pub const MAX_GROUPS: usize = 4;
+
+/// The keys and name of one group, e.g. one layout of `us,ru`
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Group {
+    pub name: Option<String>,
+    pub keys: BTreeMap<Key, Output>,
+    /// Key types given explicitly, other keys get one picked when written
+    pub types: BTreeMap<Key, String>,
+}
+impl Group {
+    /// Merges another group into this one, see [`merge_key`]
+    pub fn merge(&mut self, other: Group, mode: MergeMode) {
+        let Group{name, keys, types} = other;
+        let augment = matches!(mode, MergeMode::Augment | MergeMode::Alternate);
+        if name.is_some() && !(augment && self.name.is_some()) {
+            self.name = name;
+        }
+        if mode == MergeMode::Replace {
+            for key in keys.keys().filter(|k| !types.contains_key(k)) {
+                self.types.remove(key);
+            }
+        }
+        for (key, key_type) in types {
+            if !(augment && self.types.contains_key(&key)) {
+                self.types.insert(key, key_type);
+            }
+        }
+        for (key, output) in keys {
+            merge_key(&mut self.keys, key, output, mode);
+        }
+    }
+    /// The type of a key, either the one given or the one suggested by its levels
+    pub fn key_type(&self, key: &Key) -> Option<&str> {
+        match self.types.get(key) {
+            Some(key_type) => Some(key_type),
+            None => self.keys.get(key).map(suggested_type),
+        }
+    }
+}
+/// Merges groups in order into other groups
+pub fn merge_groups(groups: &mut Vec<Group>, other: Vec<Group>, mode: MergeMode) {
+    for (i, group) in other.into_iter().enumerate() {
+        if i == groups.len() {
+            groups.push(Group::default());
+        }
+        groups[i].merge(group, mode);
+    }
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct PartialXkbSymbols {
     pub name: String,
-    pub include: Option<String>,
-    pub name_group1: Option<String>,
-    pub keys: BTreeMap<Key, Output>
+    pub includes: Vec<Include>,
+    /// The groups in order, starting with `Group1`
+    pub groups: Vec<Group>,
 }
 impl Display for PartialXkbSymbols {
     fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        WithKeysyms(self, KeysymPolicy::default()).fmt(fmt)
+    }
+}
+impl Display for WithKeysyms<'_, PartialXkbSymbols> {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        let WithKeysyms(symbols, policy) = *self;
         writeln!(fmt, "partial alphanumeric_keys")?;
-        writeln!(fmt, "xkb_symbols \"{}\" {{\n", self.name)?;
+        writeln!(fmt, "xkb_symbols {} {{\n", quote(&symbols.name))?;
 
-        if let Some(ref inc) = self.include {
-            writeln!(fmt, "    include \"{}\"\n", inc)?;
+        for Include{mode, file} in &symbols.includes {
+            writeln!(fmt, "    {} \"{}\"", mode.keyword(), file)?;
         }
-        if let Some(ref ng) = self.name_group1 {
-            writeln!(fmt, "    name[Group1]=\"{}\";\n", ng)?;
+        if !symbols.includes.is_empty() {
+            writeln!(fmt)?;
         }
+        for (i, group) in symbols.groups.iter().enumerate() {
+            if let Some(ref name) = group.name {
+                writeln!(fmt, "    name[Group{}]={};", i + 1, quote(name))?;
+            }
+        }
+        if symbols.groups.iter().any(|g| g.name.is_some()) {
+            writeln!(fmt)?;
+        }
+
+        let keys: BTreeSet<&Key> = symbols.groups.iter().flat_map(|g| g.keys.keys()).collect();
+        for key in keys {
+            let outputs: Vec<_> = symbols.groups.iter().map(|g| g.keys.get(key)).collect();
+            let last = outputs.iter().rposition(Option::is_some).unwrap();
+            // Groups can only be left out with explicit group indices
+            let explicit = outputs[..last].iter().any(Option::is_none);
 
-        for (key, out) in self.keys.iter() {
-            writeln!(fmt, "    key <{:?}>\t{{ [{}]\t}};", key, out)?;
+            write!(fmt, "    key <{}>\t{{ ", key)?;
+            let mut first = true;
+            // Types are only written when XKB wouldn't pick the right one itself
+            for (i, (group, out)) in symbols.groups.iter().zip(&outputs).enumerate() {
+                if let Some(out) = out {
+                    let key_type = group.key_type(key).unwrap();
+                    if key_type != automatic_type(out) {
+                        if !first {
+                            write!(fmt, ", ")?;
+                        }
+                        first = false;
+                        write!(fmt, "type[Group{}]= \"{}\"", i + 1, key_type)?;
+                    }
+                }
+            }
+            for (i, out) in outputs.iter().enumerate() {
+                if let Some(out) = out {
+                    if !first {
+                        write!(fmt, ", ")?;
+                    }
+                    first = false;
+                    if explicit {
+                        write!(fmt, "symbols[Group{}]= ", i + 1)?;
+                    }
+                    write!(fmt, "[{}]", WithKeysyms(*out, policy))?;
+                }
+            }
+            writeln!(fmt, "\t}};")?;
         }
         write!(fmt, "}};")
     }
@@ -217,112 +490,211 @@ impl PartialXkbSymbols {
     pub fn new(name: String) -> Self {
         PartialXkbSymbols {
             name,
-            include: None,
-            name_group1: None,
-            keys: BTreeMap::new(),
-        }
-    }
-    fn process_line(&mut self, line: &str) {
-        if line.starts_with("key ") {
-            let key = Key::from_str(&line[5..9]).unwrap();
-            let start_index = line.find('[').unwrap() + 1;
-            let end_index = start_index + line[start_index..].find(']').unwrap();
-
-            let mut chars = line[start_index..end_index]
-                .split(',')
-                .map(|s| {
-                    let s = s.trim();
-                    let dead = s.starts_with("dead_");
-                    if dead {
-                        let s = &s[5..];
-                        Character::Dead(s.to_owned().into_boxed_str())
-                    } else {
-                        Character::Char(name_to_char(s).unwrap())
-                    }
-                });
-
-            let output = Output {
-                normal: chars.next().unwrap_or_default(),
-                shift: chars.next().unwrap_or_default(),
-                altgr: chars.next().unwrap_or_default(),
-                altgr_shift: chars.next().unwrap_or_default(),
-            };
-            
-            self.keys.insert(key, output);
-        } else if line.starts_with("include ") {
-            if self.include.is_none() {
-                let start_index = line.find('"').unwrap() + 1;
-                let end_index = start_index + line[start_index..].find('"').unwrap();
-                let inc_spec = line[start_index..end_index].to_owned();
-
-                self.include = Some(inc_spec);
+            includes: Vec::new(),
+            groups: Vec::new(),
+        }
+    }
+    /// The group with the given number, starting at 1, added if it doesn't exist yet
+    pub fn group_mut(&mut self, group: usize) -> &mut Group {
+        group_mut(&mut self.groups, group)
+    }
+    /// The same symbols with only one group, for formats that have only one
+    ///
+    /// The group, numbered from 1, becomes the first group and the others are dropped.
+    pub fn select_group(&self, group: usize) -> Self {
+        PartialXkbSymbols {
+            name: self.name.clone(),
+            includes: self.includes.clone(),
+            groups: self.groups.get(group.wrapping_sub(1)).cloned().into_iter().collect(),
+        }
+    }
+    /// Makes the model of a parsed section
+    ///
+    /// Only the first four levels of each group are kept, and key types only where they differ
+    /// from the suggested ones.
+    pub fn from_section(section: &Section) -> Result<Self> {
+        let mut symbols = PartialXkbSymbols::new(section.name.clone());
+        let mut default_types = DefaultTypes::new();
+
+        for statement in &section.statements {
+            match *statement {
+                Statement::Include{mode, ref file} => {
+                    symbols.includes.push(Include{mode, file: file.clone()});
+                }
+                ref statement => apply_statement(&mut symbols.groups, statement, &mut default_types)?,
             }
-        } else if line.starts_with("name[Group1]") {
-            let start_index = line.find('"').unwrap() + 1;
-            let end_index = start_index + line[start_index..].find('"').unwrap();
-            let inc_spec = line[start_index..end_index].to_owned();
+        }
+        // Types that would be picked anyway aren't kept, so written files read back the same
+        for group in &mut symbols.groups {
+            let Group{keys, types, ..} = group;
+            types.retain(|key, key_type| keys.get(key).is_none_or(|out| suggested_type(out) != key_type));
+        }
 
-            self.name_group1 = Some(inc_spec);
-        } else {
-            eprintln!("Unexpected line {:?}", line);
+        Ok(symbols)
+    }
+}
+
+/// Key types set by `key.type` statements for the keys after them, by group or for every group
+type DefaultTypes = BTreeMap<Option<usize>, String>;
+
+/// The types given in a key statement or a `key.type` statement by group, or for every group
+fn field_types<'a, I: IntoIterator<Item=&'a Field>>(fields: I, field_name: &str) -> Result<DefaultTypes> {
+    let mut types = DefaultTypes::new();
+    for field in fields {
+        if let (Some(name), Value::Str(key_type)) = (&field.name, &field.value) {
+            if name.eq_ignore_ascii_case(field_name) {
+                let group = match field.index {
+                    Some(_) => Some(group_index(field.group())?),
+                    None => None,
+                };
+                types.insert(group, key_type.clone());
+            }
         }
     }
+    Ok(types)
 }
-#[derive(Debug, Clone)]
-pub struct Layout {
-    pub default_partial: PartialXkbSymbols,
-    pub partials: Vec<PartialXkbSymbols>,
+
+/// Applies a statement other than an include to the groups of a section
+fn apply_statement(groups: &mut Vec<Group>, statement: &Statement, default_types: &mut DefaultTypes) -> Result<()> {
+    match *statement {
+        Statement::Assign(ref field @ Field{value: Value::Str(ref name), ..}) if field.name.as_deref() == Some("name") => {
+            let group = group_index(field.group())?;
+            group_mut(groups, group).name = Some(name.clone());
+        }
+        Statement::Assign(ref field) => default_types.extend(field_types(Some(field), "key.type")?),
+        Statement::Key{mode, ref name, ref fields} => {
+            let key = Key::from_name(name);
+            let types = field_types(fields, "type")?;
+            for (group, output) in key_outputs(fields)? {
+                let key_type = [&types, &*default_types].iter()
+                    .find_map(|t| t.get(&Some(group)).or_else(|| t.get(&None)));
+                let other = Group {
+                    keys: Some((key.clone(), output)).into_iter().collect(),
+                    types: key_type.map(|t| (key.clone(), t.clone())).into_iter().collect(),
+                    name: None,
+                };
+                group_mut(groups, group).merge(other, mode);
+            }
+        }
+        _ => (),
+    }
+    Ok(())
 }
 
-use std::io::{Result, BufReader, BufRead, Read, Write};
+fn group_mut(groups: &mut Vec<Group>, group: usize) -> &mut Group {
+    if groups.len() < group {
+        groups.resize_with(group, Group::default);
+    }
+    &mut groups[group - 1]
+}
 
-impl Layout {
-    pub fn from_reader<R: Read>(reader: R) -> Result<Self> {
-        let reader = BufReader::new(reader);
+/// A string as XKB files quote it, with `"` and `\` escaped
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
 
-        let mut cur_partial = None;
-        let mut is_default = None;
-        let mut partials = Vec::new();
-        let mut default_partial = None;
+fn group_index(group: Option<u8>) -> Result<usize> {
+    match group {
+        Some(g) if g as usize <= MAX_GROUPS => Ok(g as usize),
+        _ => Err(Error::new(ErrorKind::InvalidData, "invalid group")),
+    }
+}
 
-        for line in reader.lines() {
-            let line = line?;
-            let line = line.trim();
-            if line.is_empty() || line.starts_with("//") {
-                continue;
+/// The outputs of the groups of a key statement with the number of the group
+///
+/// The groups are either given as bare lists in order or as `symbols[Groupn]`.
+fn key_outputs(fields: &[Field]) -> Result<Vec<(usize, Output)>> {
+    let mut outputs = Vec::new();
+    let mut bare = 0;
+    for field in fields {
+        let group = match field.name {
+            None => {
+                bare += 1;
+                bare
             }
+            Some(ref name) if name == "symbols" => match field.index {
+                Some(_) => group_index(field.group())?,
+                None => 1,
+            },
+            _ => continue,
+        };
+        let levels = match field.value {
+            Value::List(ref levels) if !levels.is_empty() => levels,
+            _ => continue,
+        };
+        let mut chars = levels.iter().map(keysym);
+        outputs.push((group, Output {
+            normal: chars.next().transpose()?.unwrap_or_default(),
+            shift: chars.next().transpose()?.unwrap_or_default(),
+            altgr: chars.next().transpose()?.unwrap_or_default(),
+            altgr_shift: chars.next().transpose()?.unwrap_or_default(),
+        }));
+    }
+    if bare > MAX_GROUPS {
+        return Err(Error::new(ErrorKind::InvalidData, "too many groups"));
+    }
+    Ok(outputs)
+}
 
-            match (is_default, &mut cur_partial) {
-                (None, None) => {
-                    assert!(line.contains("partial"));
-                    let isd = line.starts_with("default");
+/// Merges the levels of a key into a symbol map
+///
+/// Augmenting only fills in levels that aren't set yet, replacing drops the old levels
+/// and overriding, the default, keeps old levels only where the new ones aren't set.
+pub fn merge_key(keys: &mut BTreeMap<Key, Output>, key: Key, output: Output, mode: MergeMode) {
+    match keys.get_mut(&key) {
+        Some(old) => match mode {
+            MergeMode::Augment | MergeMode::Alternate => *old |= output,
+            MergeMode::Replace => *old = output,
+            MergeMode::Default | MergeMode::Override => *old = output | old.clone(),
+        },
+        None => {
+            keys.insert(key, output);
+        }
+    }
+}
 
-                    if isd {
-                        assert!(default_partial.is_none());
-                    }
+/// The character of a level, the first keysym being used if there are several
+fn keysym(value: &Value) -> Result<Character> {
+    match value {
+        Value::Ident(name) => Ok(Character::from_name(name)),
+        Value::Set(syms) => syms.first().map_or(Ok(Character::default()), keysym),
+        v => Err(Error::new(ErrorKind::InvalidData, format!("invalid keysym {:?}", v))),
+    }
+}
 
-                    is_default = Some(isd);
-                }
-                (Some(_), None) => {
-                    assert!(line.starts_with("xkb_symbols "));
-                    let end_index = line.rfind('"').unwrap();
-                    let name = line[13..end_index].to_owned();
-                    cur_partial = Some(PartialXkbSymbols::new(name));
-                }
-                (Some(default), ref mut cur_partial @ Some(_)) if line.starts_with("}") => {
-                    let partial = cur_partial.take().unwrap();
-                    is_default = None;
-
-                    if default {
-                        default_partial = Some(partial);
-                    } else {
-                        partials.push(partial);
-                    }
-                }
-                (Some(_), Some(ref mut cur_partial)) => {
-                    cur_partial.process_line(line);
-                }
-                (None, Some(_)) => unreachable!(),
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    pub default_partial: PartialXkbSymbols,
+    pub partials: Vec<PartialXkbSymbols>,
+}
+
+use std::io::{Result, Error, ErrorKind, Read, Write};
+
+impl Layout {
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self> {
+        let mut src = String::new();
+        reader.read_to_string(&mut src)?;
+
+        Self::from_file(&SymbolsFile::parse(&src)?)
+    }
+    /// Makes the model of a parsed file
+    ///
+    /// Without a section marked `default`, the first one is the default.
+    pub fn from_file(file: &SymbolsFile) -> Result<Self> {
+        let sections: Vec<_> = file.sections.iter().filter(|s| s.kind == "xkb_symbols").collect();
+        let default = sections.iter().position(|s| s.is_default()).unwrap_or(0);
+        if sections.is_empty() {
+            return Err(Error::new(ErrorKind::InvalidData, "no xkb_symbols section"));
+        }
+
+        let mut partials = Vec::with_capacity(sections.len() - 1);
+        let mut default_partial = None;
+        for (i, section) in sections.into_iter().enumerate() {
+            let partial = PartialXkbSymbols::from_section(section)?;
+            if i == default {
+                default_partial = Some(partial);
+            } else {
+                partials.push(partial);
             }
         }
 
@@ -340,13 +712,17 @@ impl Layout {
             None
         }
     }
-    pub fn write<W: Write>(&self, mut writer: W) -> Result<()> {
-        let &Layout{ref default_partial, ref partials} = self;
+    pub fn write<W: Write>(&self, writer: W) -> Result<()> {
+        self.write_with_keysyms(writer, KeysymPolicy::default())
+    }
+    /// Writes the layout with characters named according to a policy
+    pub fn write_with_keysyms<W: Write>(&self, mut writer: W, policy: KeysymPolicy) -> Result<()> {
+        let Layout{default_partial, partials} = self;
 
-        writeln!(writer, "default  {}\n", default_partial)?;
+        writeln!(writer, "default  {}\n", WithKeysyms(default_partial, policy))?;
 
         for partial in partials {
-            writeln!(writer, "{}\n", partial)?;
+            writeln!(writer, "{}\n", WithKeysyms(partial, policy))?;
         }
 
         Ok(())
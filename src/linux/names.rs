@@ -1,42 +1,67 @@
-use lazy_static::lazy_static;
-use std::collections::HashMap;
+//! Tables of keysym names generated from `linux-keysymdef` when building
+//!
+//! They are sorted, looked up by binary search, and need neither allocation nor `std`.
 
 pub struct BiMap {
-    name_char: HashMap<&'static str, char>,
-    char_name: HashMap<char, &'static str>,
+    /// Every name with its keysym, its character and whether it's deprecated, by name
+    by_name: &'static [(&'static str, u32, Option<char>, bool)],
+    /// The first name and character of each keysym, by keysym
+    by_keysym: &'static [(u32, &'static str, Option<char>)],
+    /// The first name of each character and its first name that isn't deprecated, by character
+    by_char: &'static [(char, &'static str, Option<&'static str>)],
 }
 
 impl BiMap {
     #[inline]
-    fn new() -> Self {
-        BiMap {
-            name_char: HashMap::new(),
-            char_name: HashMap::new(),
-        }
+    fn name(&self, name: &str) -> Option<&(&'static str, u32, Option<char>, bool)> {
+        let i = self.by_name.binary_search_by(|e| e.0.cmp(name)).ok()?;
+        Some(&self.by_name[i])
     }
     #[inline]
-    fn insert(&mut self, name: &'static str, c: char) {
-        self.name_char.insert(name, c);
-        if !self.char_name.contains_key(&c) {
-            self.char_name.insert(c, name);
-        }
+    fn keysym(&self, keysym: u32) -> Option<&(u32, &'static str, Option<char>)> {
+        let i = self.by_keysym.binary_search_by(|e| e.0.cmp(&keysym)).ok()?;
+        Some(&self.by_keysym[i])
+    }
+    #[inline]
+    fn char(&self, c: char) -> Option<&(char, &'static str, Option<&'static str>)> {
+        let i = self.by_char.binary_search_by(|e| e.0.cmp(&c)).ok()?;
+        Some(&self.by_char[i])
+    }
+    /// Every name with its keysym and character, by name
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, u32, Option<char>)> {
+        self.by_name.iter().map(|&(name, keysym, c, _)| (name, keysym, c))
     }
     #[inline]
     pub fn get_char(&self, name: &str) -> Option<char> {
-        self.name_char.get(&name).copied()
+        self.name(name)?.2
     }
+    /// The first name of a character, the canonical one
     #[inline]
     pub fn get_name(&self, c: char) -> Option<&'static str> {
-        self.char_name.get(&c).copied()
+        self.char(c).map(|e| e.1)
+    }
+    /// The first name of a character that isn't deprecated
+    #[inline]
+    pub fn get_current_name(&self, c: char) -> Option<&'static str> {
+        self.char(c)?.2
+    }
+    #[inline]
+    pub fn is_deprecated(&self, name: &str) -> bool {
+        self.name(name).is_some_and(|e| e.3)
+    }
+    #[inline]
+    pub fn get_keysym(&self, name: &str) -> Option<u32> {
+        self.name(name).map(|e| e.1)
+    }
+    /// The first name of a keysym value
+    #[inline]
+    pub fn get_keysym_name(&self, keysym: u32) -> Option<&'static str> {
+        self.keysym(keysym).map(|e| e.1)
+    }
+    #[inline]
+    pub fn get_keysym_char(&self, keysym: u32) -> Option<char> {
+        self.keysym(keysym)?.2
     }
 }
 
-lazy_static! {
-    pub static ref NAMES: BiMap = {
-        let mut hash = include!(concat!(env!("OUT_DIR"), "/keysymdef.rs"));
-        hash.insert("NoSymbol", '\0');
-        hash.name_char.shrink_to_fit();
-        hash.char_name.shrink_to_fit();
-        hash
-    };
-}
+pub static NAMES: BiMap = include!(concat!(env!("OUT_DIR"), "/keysymdef.rs"));
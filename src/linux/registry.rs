@@ -0,0 +1,230 @@
+//! Entries of the XKB registry, `evdev.xml` and `evdev.lst`, which desktops list layouts from
+
+use super::Layout;
+use crate::Metadata;
+
+use std::io::{Result, Error, ErrorKind, Write};
+
+/// ISO 639-1 codes and the ISO 639-3 codes the registry uses
+const ISO_639: &[(&str, &str)] = &[
+    ("aa", "aar"), ("ab", "abk"), ("ae", "ave"), ("af", "afr"), ("ak", "aka"), ("am", "amh"),
+    ("an", "arg"), ("ar", "ara"), ("as", "asm"), ("av", "ava"), ("ay", "aym"), ("az", "aze"),
+    ("ba", "bak"), ("be", "bel"), ("bg", "bul"), ("bi", "bis"), ("bm", "bam"), ("bn", "ben"),
+    ("bo", "bod"), ("br", "bre"), ("bs", "bos"), ("ca", "cat"), ("ce", "che"), ("ch", "cha"),
+    ("co", "cos"), ("cr", "cre"), ("cs", "ces"), ("cu", "chu"), ("cv", "chv"), ("cy", "cym"),
+    ("da", "dan"), ("de", "deu"), ("dv", "div"), ("dz", "dzo"), ("ee", "ewe"), ("el", "ell"),
+    ("en", "eng"), ("eo", "epo"), ("es", "spa"), ("et", "est"), ("eu", "eus"), ("fa", "fas"),
+    ("ff", "ful"), ("fi", "fin"), ("fj", "fij"), ("fo", "fao"), ("fr", "fra"), ("fy", "fry"),
+    ("ga", "gle"), ("gd", "gla"), ("gl", "glg"), ("gn", "grn"), ("gu", "guj"), ("gv", "glv"),
+    ("ha", "hau"), ("he", "heb"), ("hi", "hin"), ("ho", "hmo"), ("hr", "hrv"), ("ht", "hat"),
+    ("hu", "hun"), ("hy", "hye"), ("hz", "her"), ("ia", "ina"), ("id", "ind"), ("ie", "ile"),
+    ("ig", "ibo"), ("ii", "iii"), ("ik", "ipk"), ("io", "ido"), ("is", "isl"), ("it", "ita"),
+    ("iu", "iku"), ("ja", "jpn"), ("jv", "jav"), ("ka", "kat"), ("kg", "kon"), ("ki", "kik"),
+    ("kj", "kua"), ("kk", "kaz"), ("kl", "kal"), ("km", "khm"), ("kn", "kan"), ("ko", "kor"),
+    ("kr", "kau"), ("ks", "kas"), ("ku", "kur"), ("kv", "kom"), ("kw", "cor"), ("ky", "kir"),
+    ("la", "lat"), ("lb", "ltz"), ("lg", "lug"), ("li", "lim"), ("ln", "lin"), ("lo", "lao"),
+    ("lt", "lit"), ("lu", "lub"), ("lv", "lav"), ("mg", "mlg"), ("mh", "mah"), ("mi", "mri"),
+    ("mk", "mkd"), ("ml", "mal"), ("mn", "mon"), ("mr", "mar"), ("ms", "msa"), ("mt", "mlt"),
+    ("my", "mya"), ("na", "nau"), ("nb", "nob"), ("nd", "nde"), ("ne", "nep"), ("ng", "ndo"),
+    ("nl", "nld"), ("nn", "nno"), ("no", "nor"), ("nr", "nbl"), ("nv", "nav"), ("ny", "nya"),
+    ("oc", "oci"), ("oj", "oji"), ("om", "orm"), ("or", "ori"), ("os", "oss"), ("pa", "pan"),
+    ("pi", "pli"), ("pl", "pol"), ("ps", "pus"), ("pt", "por"), ("qu", "que"), ("rm", "roh"),
+    ("rn", "run"), ("ro", "ron"), ("ru", "rus"), ("rw", "kin"), ("sa", "san"), ("sc", "srd"),
+    ("sd", "snd"), ("se", "sme"), ("sg", "sag"), ("sh", "hbs"), ("si", "sin"), ("sk", "slk"),
+    ("sl", "slv"), ("sm", "smo"), ("sn", "sna"), ("so", "som"), ("sq", "sqi"), ("sr", "srp"),
+    ("ss", "ssw"), ("st", "sot"), ("su", "sun"), ("sv", "swe"), ("sw", "swa"), ("ta", "tam"),
+    ("te", "tel"), ("tg", "tgk"), ("th", "tha"), ("ti", "tir"), ("tk", "tuk"), ("tl", "tgl"),
+    ("tn", "tsn"), ("to", "ton"), ("tr", "tur"), ("ts", "tso"), ("tt", "tat"), ("tw", "twi"),
+    ("ty", "tah"), ("ug", "uig"), ("uk", "ukr"), ("ur", "urd"), ("uz", "uzb"), ("ve", "ven"),
+    ("vi", "vie"), ("vo", "vol"), ("wa", "wln"), ("wo", "wol"), ("xh", "xho"), ("yi", "yid"),
+    ("yo", "yor"), ("za", "zha"), ("zh", "zho"), ("zu", "zul"),
+];
+
+/// The ISO 639-3 language and ISO 3166 country codes of a locale like `da-DK` or `sr_Latn_RS`
+pub fn locale_codes(locale: &str) -> (Option<String>, Option<String>) {
+    let mut parts = locale.split(['-', '_', '.']);
+    let language = parts.next().map(str::to_ascii_lowercase).and_then(|l| match l.len() {
+        2 => ISO_639.binary_search_by_key(&&*l, |&(two, _)| two).ok().map(|i| ISO_639[i].1.to_owned()),
+        3 if l.bytes().all(|b| b.is_ascii_lowercase()) => Some(l),
+        _ => None,
+    });
+    // The script, if any, comes before the country
+    let country = parts.find(|p| p.len() == 2 && p.bytes().all(|b| b.is_ascii_alphabetic()))
+        .map(str::to_ascii_uppercase);
+    (language, country)
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('"', "&quot;")
+}
+
+fn unescape(s: &str) -> String {
+    s.replace("&lt;", "<").replace("&gt;", ">").replace("&quot;", "\"").replace("&amp;", "&")
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Variant {
+    pub name: String,
+    pub description: String,
+}
+
+/// The registry entry of a symbols file, its default section being the layout and the others variants
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegistryEntry {
+    /// The name of the symbols file
+    pub name: String,
+    /// The short name shown in panels, e.g. `dk`
+    pub short_description: Option<String>,
+    pub description: String,
+    pub countries: Vec<String>,
+    pub languages: Vec<String>,
+    pub variants: Vec<Variant>,
+}
+
+impl RegistryEntry {
+    /// Makes the entry of a layout installed as the symbols file `name`
+    ///
+    /// The description is the name of the metadata, or else the name of the first group,
+    /// and variants are described by the name of their first group if it's a different one.
+    pub fn new(name: &str, metadata: &Metadata, layout: &Layout) -> Self {
+        let group_name = |p: &super::PartialXkbSymbols| p.groups.first().and_then(|g| g.name.clone());
+        let description = Some(metadata.name.clone()).filter(|n| !n.is_empty())
+            .or_else(|| group_name(&layout.default_partial))
+            .unwrap_or_else(|| name.to_owned());
+        let (language, country) = locale_codes(&metadata.locale);
+
+        RegistryEntry {
+            name: name.to_owned(),
+            short_description: Some(metadata.short.clone()).filter(|s| !s.is_empty()),
+            variants: layout.partials.iter().map(|p| Variant {
+                name: p.name.clone(),
+                description: group_name(p).filter(|n| *n != description)
+                    .unwrap_or_else(|| format!("{} ({})", description, p.name)),
+            }).collect(),
+            description,
+            countries: country.into_iter().collect(),
+            languages: language.into_iter().collect(),
+        }
+    }
+    fn write_config_item<W: Write>(writer: &mut W, indent: &str, name: &str, short: Option<&str>, description: &str, countries: &[String], languages: &[String]) -> Result<()> {
+        writeln!(writer, "{}<configItem>", indent)?;
+        writeln!(writer, "{}  <name>{}</name>", indent, escape(name))?;
+        if let Some(short) = short {
+            writeln!(writer, "{}  <shortDescription>{}</shortDescription>", indent, escape(short))?;
+        }
+        writeln!(writer, "{}  <description>{}</description>", indent, escape(description))?;
+        if !countries.is_empty() {
+            writeln!(writer, "{}  <countryList>", indent)?;
+            for country in countries {
+                writeln!(writer, "{}    <iso3166Id>{}</iso3166Id>", indent, escape(country))?;
+            }
+            writeln!(writer, "{}  </countryList>", indent)?;
+        }
+        if !languages.is_empty() {
+            writeln!(writer, "{}  <languageList>", indent)?;
+            for language in languages {
+                writeln!(writer, "{}    <iso639Id>{}</iso639Id>", indent, escape(language))?;
+            }
+            writeln!(writer, "{}  </languageList>", indent)?;
+        }
+        writeln!(writer, "{}</configItem>", indent)
+    }
+    /// Writes the `<layout>` element that goes into the `<layoutList>` of `evdev.xml`
+    pub fn write_xml<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "    <layout>")?;
+        Self::write_config_item(&mut writer, "      ", &self.name, self.short_description.as_deref(), &self.description, &self.countries, &self.languages)?;
+        if !self.variants.is_empty() {
+            writeln!(writer, "      <variantList>")?;
+            for variant in &self.variants {
+                writeln!(writer, "        <variant>")?;
+                Self::write_config_item(&mut writer, "          ", &variant.name, None, &variant.description, &[], &[])?;
+                writeln!(writer, "        </variant>")?;
+            }
+            writeln!(writer, "      </variantList>")?;
+        }
+        writeln!(writer, "    </layout>")
+    }
+    /// Writes the `! layout` and `! variant` lines of `evdev.lst`
+    pub fn write_lst<W: Write>(&self, mut writer: W) -> Result<()> {
+        writeln!(writer, "! layout")?;
+        writeln!(writer, "  {:<15} {}", self.name, self.description)?;
+        if !self.variants.is_empty() {
+            writeln!(writer, "\n! variant")?;
+            for variant in &self.variants {
+                writeln!(writer, "  {:<15} {}: {}", variant.name, self.name, variant.description)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Writes a whole `evdev.xml`, e.g. `~/.config/xkb/rules/evdev.xml`, which libxkbcommon
+/// adds to the system registry
+pub fn write_registry<W: Write>(mut writer: W, entries: &[RegistryEntry]) -> Result<()> {
+    writeln!(writer, "<?xml version=\"1.0\" encoding=\"UTF-8\"?>")?;
+    writeln!(writer, "<!DOCTYPE xkbConfigRegistry SYSTEM \"xkb.dtd\">")?;
+    writeln!(writer, "<xkbConfigRegistry version=\"1.1\">")?;
+    writeln!(writer, "  <layoutList>")?;
+    for entry in entries {
+        entry.write_xml(&mut writer)?;
+    }
+    writeln!(writer, "  </layoutList>")?;
+    writeln!(writer, "</xkbConfigRegistry>")
+}
+
+/// Where the `<layout>` element of a layout is in an `evdev.xml`, with its indentation and line end
+fn find_layout(xml: &str, name: &str) -> Option<(usize, usize)> {
+    let name = format!("<name>{}</name>", escape(name));
+    let mut from = 0;
+    while let Some(start) = xml[from..].find("<layout>").map(|i| from + i) {
+        let end = xml[start..].find("</layout>").map(|i| start + i + "</layout>".len())?;
+        // The name of the layout comes before those of its variants
+        let element = &xml[start..end];
+        if element.find("<name>").is_some_and(|i| element[i..].starts_with(&name)) {
+            let start = xml[..start].rfind('\n').map_or(0, |i| i + 1);
+            let end = if xml[end..].starts_with('\n') { end + 1 } else { end };
+            return Some((start, end));
+        }
+        from = end;
+    }
+    None
+}
+
+/// The start of the line of a tag
+fn line_of(xml: &str, tag: &str) -> Option<usize> {
+    xml.find(tag).map(|i| xml[..i].rfind('\n').map_or(0, |i| i + 1))
+}
+
+/// Adds an entry to the text of an `evdev.xml`, in place of any entry of the same name
+///
+/// Everything else in the file is kept as it is.
+pub fn add_to_registry(xml: &str, entry: &RegistryEntry) -> Result<String> {
+    let xml = remove_from_registry(xml, &entry.name).unwrap_or_else(|| xml.to_owned());
+    let mut element = Vec::new();
+    entry.write_xml(&mut element)?;
+    let element = String::from_utf8(element).unwrap();
+
+    let (at, insert) = match (line_of(&xml, "</layoutList>"), line_of(&xml, "</xkbConfigRegistry>")) {
+        (Some(at), _) => (at, element),
+        (None, Some(at)) => (at, format!("  <layoutList>\n{}  </layoutList>\n", element)),
+        (None, None) => return Err(Error::new(ErrorKind::InvalidData, "not an XKB registry")),
+    };
+    Ok(format!("{}{}{}", &xml[..at], insert, &xml[at..]))
+}
+
+/// The names of the variants of a layout in the text of an `evdev.xml`, `None` if it has no entry
+pub fn registry_variants(xml: &str, name: &str) -> Option<Vec<String>> {
+    let (start, end) = find_layout(xml, name)?;
+    let element = &xml[start..end];
+    Some(element.split("<variant>").skip(1).filter_map(|variant| {
+        let start = variant.find("<name>")? + "<name>".len();
+        let end = start + variant[start..].find("</name>")?;
+        Some(unescape(&variant[start..end]))
+    }).collect())
+}
+
+/// Removes the entry of a layout from the text of an `evdev.xml`, `None` if it has none
+pub fn remove_from_registry(xml: &str, name: &str) -> Option<String> {
+    let (start, end) = find_layout(xml, name)?;
+    Some(format!("{}{}", &xml[..start], &xml[end..]))
+}
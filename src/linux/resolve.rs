@@ -0,0 +1,201 @@
+//! Finding included symbols in XKB directory trees and flattening them
+
+use super::{Group, PartialXkbSymbols, SymbolsFile, Section, Statement, MergeMode, Include, KeyType};
+use super::{DefaultTypes, apply_statement, group_index, merge_groups};
+
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+use std::env::var_os;
+use std::fs::{read_dir, read_to_string};
+use std::io::{Result, Error, ErrorKind};
+use std::path::PathBuf;
+
+/// One file of an include string like `pc+us(intl)|level3(ralt_switch)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IncludeSegment {
+    /// The mode of the statement for the first file, then override for `+` and augment for `|`
+    pub mode: MergeMode,
+    pub file: String,
+    /// The section in parentheses, the default section if there's none
+    pub section: Option<String>,
+    /// The group the symbols go into, from a `:n` suffix
+    pub group: Option<u8>,
+}
+
+/// Splits an include string into its files
+pub fn parse_include(include: &str, mode: MergeMode) -> Vec<IncludeSegment> {
+    let mut segments = Vec::new();
+    let mut mode = mode;
+    let mut rest = include.trim();
+
+    while !rest.is_empty() {
+        let end = rest.find(['+', '|']).unwrap_or(rest.len());
+        let mut segment = &rest[..end];
+
+        let group = match segment.rfind(':') {
+            Some(i) => {
+                let group = segment[i+1..].trim().parse().ok();
+                segment = &segment[..i];
+                group
+            }
+            None => None,
+        };
+        let (file, section) = match segment.find('(') {
+            Some(i) => (&segment[..i], Some(segment[i+1..].trim_end_matches(')').to_owned())),
+            None => (segment, None),
+        };
+        if !file.is_empty() {
+            segments.push(IncludeSegment {
+                mode,
+                file: file.to_owned(),
+                section,
+                group,
+            });
+        }
+
+        mode = match rest[end..].chars().next() {
+            Some('|') => MergeMode::Augment,
+            _ => MergeMode::Override,
+        };
+        rest = rest.get(end+1..).unwrap_or("");
+    }
+
+    segments
+}
+
+/// The XKB directory of the user, `$XDG_CONFIG_HOME/xkb` or `~/.config/xkb`
+pub fn user_root() -> Option<PathBuf> {
+    var_os("XDG_CONFIG_HOME").map(PathBuf::from)
+        .or_else(|| var_os("HOME").map(|h| PathBuf::from(h).join(".config")))
+        .map(|config| config.join("xkb"))
+}
+
+fn not_found(what: String) -> Error {
+    Error::new(ErrorKind::NotFound, what)
+}
+
+/// Resolves includes by looking for symbols files in XKB directory trees
+#[derive(Debug, Clone, Default)]
+pub struct Resolver {
+    /// Directories containing a `symbols` directory, searched in order
+    pub roots: Vec<PathBuf>,
+    /// Files looked up by name before the roots, e.g. a layout that isn't installed
+    pub files: BTreeMap<String, SymbolsFile>,
+}
+
+impl Resolver {
+    pub fn new(roots: Vec<PathBuf>) -> Self {
+        Resolver {
+            roots,
+            files: BTreeMap::new(),
+        }
+    }
+    /// Searches the same places as libxkbcommon
+    ///
+    /// That is `$XDG_CONFIG_HOME/xkb` (or `~/.config/xkb`), `~/.xkb`, `$XKB_CONFIG_EXTRA_PATH`
+    /// if set and `$XKB_CONFIG_ROOT` (or `/usr/share/X11/xkb`).
+    pub fn from_env() -> Self {
+        let home = var_os("HOME").map(PathBuf::from);
+        let mut roots = Vec::new();
+
+        roots.extend(user_root());
+        if let Some(ref home) = home {
+            roots.push(home.join(".xkb"));
+        }
+        if let Some(extra) = var_os("XKB_CONFIG_EXTRA_PATH") {
+            roots.push(extra.into());
+        }
+        roots.push(var_os("XKB_CONFIG_ROOT").map_or_else(|| PathBuf::from("/usr/share/X11/xkb"), PathBuf::from));
+
+        Self::new(roots)
+    }
+    /// Makes a file available to includes under the given name
+    pub fn add_file(&mut self, name: String, file: SymbolsFile) {
+        self.files.insert(name, file);
+    }
+    /// Finds and parses a symbols file
+    pub fn load(&self, name: &str) -> Result<Cow<'_, SymbolsFile>> {
+        if let Some(file) = self.files.get(name) {
+            return Ok(Cow::Borrowed(file));
+        }
+        for root in &self.roots {
+            let path = root.join("symbols").join(name);
+            if path.is_file() {
+                let file = SymbolsFile::parse(&read_to_string(&path)?)
+                    .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?;
+                return Ok(Cow::Owned(file));
+            }
+        }
+        Err(not_found(format!("symbols file {:?} not found", name)))
+    }
+    /// The symbols file of the country of a locale like `da-DK`, if there's one
+    pub fn layout_for_locale(&self, locale: &str) -> Option<String> {
+        let name = super::locale_codes(locale).1?.to_ascii_lowercase();
+        self.load(&name).ok().map(|_| name)
+    }
+    /// The types of every file in the `types` directories, those of earlier roots first
+    pub fn types(&self) -> Result<Vec<KeyType>> {
+        let mut types = Vec::new();
+        for dir in self.roots.iter().map(|r| r.join("types")).filter(|d| d.is_dir()) {
+            let mut paths: Vec<PathBuf> = read_dir(dir)?.map(|e| e.map(|e| e.path())).collect::<Result<_>>()?;
+            paths.sort();
+            for path in paths.into_iter().filter(|p| p.is_file() && !p.ends_with("README")) {
+                types.extend(KeyType::parse_all(&read_to_string(&path)?)
+                    .map_err(|e| Error::new(e.kind(), format!("{}: {}", path.display(), e)))?);
+            }
+        }
+        Ok(types)
+    }
+    /// The groups defined by an include string like `pc+us+ru:2`, with all includes followed
+    pub fn resolve(&self, include: &str) -> Result<Vec<Group>> {
+        let mut groups = Vec::new();
+        self.include(&mut groups, include, MergeMode::Default, &mut Vec::new())?;
+        Ok(groups)
+    }
+    /// The groups of a section with its includes followed
+    pub fn flatten(&self, symbols: &PartialXkbSymbols) -> Result<Vec<Group>> {
+        let mut groups = Vec::new();
+        for Include{mode, file} in &symbols.includes {
+            self.include(&mut groups, file, *mode, &mut Vec::new())?;
+        }
+        merge_groups(&mut groups, symbols.groups.clone(), MergeMode::Override);
+        Ok(groups)
+    }
+    fn include(&self, groups: &mut Vec<Group>, include: &str, mode: MergeMode, stack: &mut Vec<(String, String)>) -> Result<()> {
+        for segment in parse_include(include, mode) {
+            let file = self.load(&segment.file)?;
+            let section = file.section(segment.section.as_deref())
+                .ok_or_else(|| not_found(format!("section {:?} not found in {:?}", segment.section.as_deref().unwrap_or("default"), segment.file)))?;
+
+            let id = (segment.file.clone(), section.name.clone());
+            if stack.contains(&id) {
+                return Err(Error::new(ErrorKind::InvalidData, format!("{}({}) includes itself", id.0, id.1)));
+            }
+            stack.push(id);
+            let mut included = Vec::new();
+            self.section(&mut included, section, stack)?;
+            stack.pop();
+
+            // With a group given, the first group of the file goes into that group
+            if let Some(group) = segment.group {
+                let group = group_index(Some(group))?;
+                included.truncate(1);
+                let mut moved = vec![Group::default(); group - 1];
+                moved.extend(included);
+                included = moved;
+            }
+            merge_groups(groups, included, segment.mode);
+        }
+        Ok(())
+    }
+    fn section(&self, groups: &mut Vec<Group>, section: &Section, stack: &mut Vec<(String, String)>) -> Result<()> {
+        let mut default_types = DefaultTypes::new();
+        for statement in &section.statements {
+            match *statement {
+                Statement::Include{mode, ref file} => self.include(groups, file, mode, stack)?,
+                ref statement => apply_statement(groups, statement, &mut default_types)?,
+            }
+        }
+        Ok(())
+    }
+}
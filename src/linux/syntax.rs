@@ -0,0 +1,566 @@
+//! Tokenizer and parser for the `xkb_symbols` grammar
+//!
+//! The syntax tree keeps everything a symbols file says, including what
+//! [`PartialXkbSymbols`](super::PartialXkbSymbols) doesn't model, such as actions.
+//! `xkb_types` sections share the grammar and are read as well.
+//! Sections, statements and values know where they are in the source so files can be edited in place.
+
+use std::io::{Result, Error, ErrorKind};
+use std::ops::Range;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token<'a> {
+    Ident(&'a str),
+    /// The contents of a string without the quotes, still escaped
+    Str(&'a str),
+    /// A keycode name without the angle brackets
+    Keycode(&'a str),
+    Punct(char),
+}
+
+/// A token and where it starts and ends in the source
+type Spanned<'a> = (usize, Token<'a>, usize);
+
+/// Where something is in the source, in bytes
+pub type Span = Range<usize>;
+
+fn error_at(src: &str, pos: usize, msg: &str) -> Error {
+    let line = src[..pos].matches('\n').count() + 1;
+    Error::new(ErrorKind::InvalidData, format!("line {}: {}", line, msg))
+}
+
+fn tokenize(src: &str) -> Result<Vec<Spanned<'_>>> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < bytes.len() {
+        let start = i;
+        let token = match bytes[i] {
+            b if b.is_ascii_whitespace() => {
+                i += 1;
+                continue;
+            }
+            b'#' => {
+                i = src[i..].find('\n').map(|n| i + n).unwrap_or(bytes.len());
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                i = src[i..].find('\n').map(|n| i + n).unwrap_or(bytes.len());
+                continue;
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = src[i+2..].find("*/").ok_or_else(|| error_at(src, start, "unterminated comment"))?;
+                i += 2 + end + 2;
+                continue;
+            }
+            b'"' => {
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    if bytes[i] == b'\\' {
+                        i += 1;
+                    }
+                    i += 1;
+                }
+                if i >= bytes.len() {
+                    return Err(error_at(src, start, "unterminated string"));
+                }
+                i += 1;
+                Token::Str(&src[start+1..i-1])
+            }
+            b'<' => {
+                let end = src[i..].find('>').ok_or_else(|| error_at(src, start, "unterminated keycode"))?;
+                i += end + 1;
+                Token::Keycode(&src[start+1..i-1])
+            }
+            b if b.is_ascii_alphanumeric() || b == b'_' => {
+                while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                    i += 1;
+                }
+                Token::Ident(&src[start..i])
+            }
+            b @ b'{' | b @ b'}' | b @ b'[' | b @ b']' | b @ b'(' | b @ b')' | b @ b';' | b @ b','
+            | b @ b'=' | b @ b'+' | b @ b'-' | b @ b'!' | b @ b'~' | b @ b'.' | b @ b'*' | b @ b'/' => {
+                i += 1;
+                Token::Punct(b as char)
+            }
+            _ => {
+                let c = src[i..].chars().next().unwrap();
+                return Err(error_at(src, start, &format!("unexpected character {:?}", c)));
+            }
+        };
+        tokens.push((start, token, i));
+    }
+
+    Ok(tokens)
+}
+
+/// Undoes the escapes XKB allows in strings
+fn unescape(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut chars = s.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => out.push('\n'),
+            Some('t') => out.push('\t'),
+            Some('r') => out.push('\r'),
+            Some('b') => out.push('\u{8}'),
+            Some('f') => out.push('\u{c}'),
+            Some('v') => out.push('\u{b}'),
+            Some('e') => out.push('\u{1b}'),
+            Some(d @ '0'..='7') => {
+                let mut n = d.to_digit(8).unwrap();
+                for _ in 0..2 {
+                    match chars.peek().and_then(|d| d.to_digit(8)) {
+                        Some(d) => {
+                            n = n * 8 + d;
+                            chars.next();
+                        }
+                        None => break,
+                    }
+                }
+                out.extend(std::char::from_u32(n));
+            }
+            Some(c) => out.push(c),
+            None => out.push('\\'),
+        }
+    }
+
+    out
+}
+
+/// How a statement combines with what is already defined
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MergeMode {
+    /// No keyword, or `include`
+    Default,
+    Augment,
+    Override,
+    Replace,
+    Alternate,
+}
+
+impl MergeMode {
+    fn from_keyword(s: &str) -> Option<Self> {
+        Some(match s {
+            "include" => MergeMode::Default,
+            "augment" => MergeMode::Augment,
+            "override" => MergeMode::Override,
+            "replace" => MergeMode::Replace,
+            "alternate" => MergeMode::Alternate,
+            _ => return None,
+        })
+    }
+    /// The keyword of an include statement with this mode
+    pub fn keyword(self) -> &'static str {
+        match self {
+            MergeMode::Default => "include",
+            MergeMode::Augment => "augment",
+            MergeMode::Override => "override",
+            MergeMode::Replace => "replace",
+            MergeMode::Alternate => "alternate",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Value {
+    Str(String),
+    /// A keysym, number or other name
+    Ident(String),
+    /// A keycode name without the angle brackets
+    Keycode(String),
+    /// `[ ... ]`, e.g. the levels of a key
+    List(Vec<Value>),
+    /// `{ ... }`, e.g. several keysyms on one level
+    Set(Vec<Value>),
+    /// An action like `SetMods(modifiers=Shift)` and its arguments as written
+    Call(String, String),
+    /// Any other expression as written, e.g. `LAlt+AltGr`
+    Expr(String),
+}
+
+/// A key field like `symbols[Group1]= [ a, A ]` or a statement like `name[Group1]= "US"`
+///
+/// Bare lists of symbols in a key have no name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    pub name: Option<String>,
+    /// What's in the brackets after the name as written, e.g. `Group1`
+    pub index: Option<String>,
+    pub value: Value,
+    /// Where the value is
+    pub span: Span,
+}
+
+/// Reads a group index like `Group2`, `group2` or `2`
+pub fn parse_group(index: &str) -> Option<u8> {
+    let index = index.trim();
+    let n = if index.len() > 5 && index[..5].eq_ignore_ascii_case("group") {
+        &index[5..]
+    } else {
+        index
+    };
+    n.parse().ok().filter(|&n| n >= 1)
+}
+
+impl Field {
+    /// The group of the index, if it's one
+    pub fn group(&self) -> Option<u8> {
+        self.index.as_ref().and_then(|i| parse_group(i))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Statement {
+    /// `include "pc+us(intl)"`, `augment "..."` and so on
+    Include {
+        mode: MergeMode,
+        file: String,
+    },
+    Key {
+        mode: MergeMode,
+        name: String,
+        fields: Vec<Field>,
+    },
+    ModifierMap {
+        mode: MergeMode,
+        modifier: String,
+        keys: Vec<Value>,
+    },
+    VirtualModifiers(Vec<String>),
+    /// A key type definition of an `xkb_types` section
+    Type {
+        name: String,
+        fields: Vec<Field>,
+    },
+    /// Any other assignment, e.g. `name[Group1]= "US"` or `key.type= "FOUR_LEVEL"`
+    Assign(Field),
+}
+
+/// One `xkb_symbols` or `xkb_types` section of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Section {
+    /// e.g. `default`, `partial` and `alphanumeric_keys`
+    pub flags: Vec<String>,
+    /// The keyword starting the section, e.g. `xkb_symbols`
+    pub kind: String,
+    pub name: String,
+    pub statements: Vec<Statement>,
+    /// Where the statements are, from their first token to their `;`
+    pub spans: Vec<Span>,
+    /// Where the whole section is, from its first flag to its `;`
+    pub span: Span,
+    /// Where the contents between the braces are
+    pub body: Span,
+}
+
+impl Section {
+    pub fn is_default(&self) -> bool {
+        self.flags.iter().any(|f| f.eq_ignore_ascii_case("default"))
+    }
+}
+
+/// The syntax tree of an XKB symbols file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SymbolsFile {
+    pub sections: Vec<Section>,
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    tokens: Vec<Spanned<'a>>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn error(&self, msg: &str) -> Error {
+        let at = self.tokens.get(self.pos).map(|t| t.0).unwrap_or_else(|| self.src.len());
+        error_at(self.src, at, msg)
+    }
+    fn peek(&self) -> Option<Token<'a>> {
+        self.tokens.get(self.pos).map(|t| t.1)
+    }
+    fn peek_nth(&self, n: usize) -> Option<Token<'a>> {
+        self.tokens.get(self.pos + n).map(|t| t.1)
+    }
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(Token::Punct(c)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+    fn expect(&mut self, c: char) -> Result<()> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected `{}`", c)))
+        }
+    }
+    /// The next token in lower case if it's a name, keywords being case insensitive
+    fn keyword(&self) -> String {
+        match self.peek() {
+            Some(Token::Ident(s)) => s.to_ascii_lowercase(),
+            _ => String::new(),
+        }
+    }
+    fn ident(&mut self) -> Result<&'a str> {
+        match self.peek() {
+            Some(Token::Ident(s)) => {
+                self.pos += 1;
+                Ok(s)
+            }
+            _ => Err(self.error("expected a name")),
+        }
+    }
+    /// The source from the start of one token to the end of the token before another
+    fn text(&self, from: usize, to: usize) -> String {
+        self.src[self.span(from, to)].to_owned()
+    }
+    /// Where the tokens from one token to the one before another are
+    fn span(&self, from: usize, to: usize) -> Span {
+        self.tokens[from].0..self.tokens[to - 1].2
+    }
+
+    fn file(&mut self) -> Result<SymbolsFile> {
+        let mut sections = Vec::new();
+        while self.peek().is_some() {
+            sections.push(self.section()?);
+        }
+        Ok(SymbolsFile{sections})
+    }
+    fn section(&mut self) -> Result<Section> {
+        let start = self.pos;
+        let mut flags = Vec::new();
+        let kind = loop {
+            let keyword = self.keyword();
+            match self.ident()? {
+                _ if keyword == "xkb_symbols" || keyword == "xkb_types" => break keyword,
+                flag => flags.push(flag.to_owned()),
+            }
+        };
+        let name = match self.peek() {
+            Some(Token::Str(s)) => {
+                self.pos += 1;
+                unescape(s)
+            }
+            _ => String::new(),
+        };
+        self.expect('{')?;
+        let body_start = self.tokens[self.pos - 1].2;
+
+        let mut statements = Vec::new();
+        let mut spans = Vec::new();
+        while self.peek() != Some(Token::Punct('}')) {
+            if self.eat(';') {
+                continue;
+            }
+            let statement_start = self.pos;
+            statements.push(self.statement()?);
+            spans.push(self.span(statement_start, self.pos));
+        }
+        let body = body_start..self.tokens[self.pos].0;
+        self.expect('}')?;
+        self.eat(';');
+
+        Ok(Section{flags, kind, name, statements, spans, span: self.span(start, self.pos), body})
+    }
+    fn statement(&mut self) -> Result<Statement> {
+        let mut mode = MergeMode::Default;
+        if let Some(m) = MergeMode::from_keyword(&self.keyword()) {
+            self.pos += 1;
+            if let Some(Token::Str(file)) = self.peek() {
+                self.pos += 1;
+                return Ok(Statement::Include{mode: m, file: unescape(file)});
+            }
+            mode = m;
+        }
+
+        let statement = match (self.keyword().as_str(), self.peek_nth(1)) {
+            ("key", Some(Token::Keycode(name))) => {
+                self.pos += 2;
+                self.expect('{')?;
+                let mut fields = Vec::new();
+                while !self.eat('}') {
+                    if self.eat(',') {
+                        continue;
+                    }
+                    fields.push(self.key_field()?);
+                }
+                Statement::Key{mode, name: name.to_owned(), fields}
+            }
+            ("modifier_map", _) | ("mod_map", _) | ("modmap", _) => {
+                self.pos += 1;
+                let modifier = self.ident()?.to_owned();
+                self.expect('{')?;
+                let keys = self.values('}')?;
+                Statement::ModifierMap{mode, modifier, keys}
+            }
+            ("type", Some(Token::Str(name))) => {
+                self.pos += 2;
+                self.expect('{')?;
+                let mut fields = Vec::new();
+                while !self.eat('}') {
+                    if self.eat(';') {
+                        continue;
+                    }
+                    fields.push(self.assignment()?);
+                    self.expect(';')?;
+                }
+                Statement::Type{name: unescape(name), fields}
+            }
+            ("virtual_modifiers", _) => {
+                self.pos += 1;
+                let mut mods = Vec::new();
+                while self.peek() != Some(Token::Punct(';')) {
+                    if self.eat(',') {
+                        continue;
+                    }
+                    let start = self.pos;
+                    self.ident()?;
+                    if self.eat('=') {
+                        self.value()?;
+                    }
+                    mods.push(self.text(start, self.pos));
+                }
+                Statement::VirtualModifiers(mods)
+            }
+            _ => Statement::Assign(self.assignment()?),
+        };
+        self.expect(';')?;
+
+        Ok(statement)
+    }
+    /// `name` or `name.field`, an optional `[index]`, `=` and a value
+    fn assignment(&mut self) -> Result<Field> {
+        let mut name = self.ident()?.to_owned();
+        if self.eat('.') {
+            name.push('.');
+            name.push_str(self.ident()?);
+        }
+        let index = if self.eat('[') {
+            let start = self.pos;
+            self.expr()?;
+            let index = self.text(start, self.pos);
+            self.expect(']')?;
+            Some(index)
+        } else {
+            None
+        };
+        self.expect('=')?;
+        let start = self.pos;
+        let value = self.value()?;
+
+        Ok(Field{name: Some(name), index, value, span: self.span(start, self.pos)})
+    }
+    fn key_field(&mut self) -> Result<Field> {
+        if self.eat('[') {
+            let start = self.pos - 1;
+            let value = Value::List(self.values(']')?);
+            Ok(Field {
+                name: None,
+                index: None,
+                value,
+                span: self.span(start, self.pos),
+            })
+        } else {
+            self.assignment()
+        }
+    }
+    /// Comma separated values up to a closing bracket
+    fn values(&mut self, close: char) -> Result<Vec<Value>> {
+        let mut values = Vec::new();
+        while !self.eat(close) {
+            if self.eat(',') {
+                continue;
+            }
+            values.push(self.value()?);
+        }
+        Ok(values)
+    }
+    fn value(&mut self) -> Result<Value> {
+        Ok(match self.peek() {
+            Some(Token::Str(s)) => {
+                self.pos += 1;
+                Value::Str(unescape(s))
+            }
+            Some(Token::Keycode(k)) => {
+                self.pos += 1;
+                Value::Keycode(k.to_owned())
+            }
+            Some(Token::Punct('[')) => {
+                self.pos += 1;
+                Value::List(self.values(']')?)
+            }
+            Some(Token::Punct('{')) => {
+                self.pos += 1;
+                Value::Set(self.values('}')?)
+            }
+            _ => {
+                let start = self.pos;
+                self.expr()?;
+                match self.tokens[start..self.pos] {
+                    [(_, Token::Ident(s), _)] => Value::Ident(s.to_owned()),
+                    [(_, Token::Ident(s), _), (_, Token::Punct('('), _), .., (_, Token::Punct(')'), _)] => {
+                        let args = if self.pos - start > 3 {
+                            self.text(start + 2, self.pos - 1)
+                        } else {
+                            String::new()
+                        };
+                        Value::Call(s.to_owned(), args)
+                    }
+                    _ => Value::Expr(self.text(start, self.pos)),
+                }
+            }
+        })
+    }
+    /// Skips the tokens of an expression, stopping before anything that ends a value
+    fn expr(&mut self) -> Result<()> {
+        let start = self.pos;
+        let mut depth = 0usize;
+        loop {
+            match self.peek() {
+                Some(Token::Punct('(')) => depth += 1,
+                Some(Token::Punct(')')) if depth > 0 => depth -= 1,
+                Some(Token::Punct(',')) | Some(Token::Punct(';')) | Some(Token::Punct(']')) | Some(Token::Punct('}'))
+                | Some(Token::Punct('=')) | Some(Token::Punct(')')) if depth == 0 => break,
+                Some(Token::Punct('{')) | Some(Token::Punct('[')) if depth == 0 => break,
+                None => break,
+                _ => (),
+            }
+            self.pos += 1;
+        }
+        if self.pos == start {
+            Err(self.error("expected a value"))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl SymbolsFile {
+    pub fn parse(src: &str) -> Result<Self> {
+        let mut parser = Parser {
+            src,
+            tokens: tokenize(src)?,
+            pos: 0,
+        };
+        parser.file()
+    }
+    /// The section with the given name, or the default one if there's no name
+    ///
+    /// Without a section marked `default`, the first one is the default.
+    pub fn section(&self, name: Option<&str>) -> Option<&Section> {
+        match name {
+            Some(name) => self.sections.iter().find(|s| s.name == name),
+            None => self.sections.iter().find(|s| s.is_default()).or_else(|| self.sections.first()),
+        }
+    }
+}
@@ -0,0 +1,225 @@
+//! XKB key types, which decide the level a key gives for the modifiers held
+
+use super::{Character, Output, SymbolsFile, Statement, Field, Value, quote};
+
+use lazy_static::lazy_static;
+
+use std::fmt::{self, Display};
+use std::io::{Result, Error, ErrorKind};
+
+/// A key type like `FOUR_LEVEL_ALPHABETIC`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyType {
+    pub name: String,
+    /// The modifiers the type pays attention to, e.g. `Shift` and `LevelThree`
+    pub modifiers: Vec<String>,
+    /// Combinations of modifiers and the level, from 1, they give
+    pub map: Vec<(Vec<String>, usize)>,
+    /// Combinations of modifiers and which of them are still seen after picking the level
+    pub preserve: Vec<(Vec<String>, Vec<String>)>,
+    pub level_names: Vec<(usize, String)>,
+}
+
+fn invalid(msg: String) -> Error {
+    Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// The modifiers of an expression like `Shift+LevelThree`, `None` being none
+fn modifiers(value: &Value) -> Result<Vec<String>> {
+    match value {
+        Value::Ident(s) | Value::Expr(s) => Ok(s.split('+')
+            .map(str::trim)
+            .filter(|m| !m.eq_ignore_ascii_case("none"))
+            .map(str::to_owned)
+            .collect()),
+        v => Err(invalid(format!("invalid modifiers {:?}", v))),
+    }
+}
+
+/// A level like `Level2` or `2`
+fn level(s: &str) -> Option<usize> {
+    let s = s.trim();
+    let n = if s.len() > 5 && s[..5].eq_ignore_ascii_case("level") {
+        &s[5..]
+    } else {
+        s
+    };
+    n.parse().ok().filter(|&n| n >= 1)
+}
+
+fn join(mods: &[String]) -> String {
+    if mods.is_empty() {
+        "None".to_owned()
+    } else {
+        mods.join("+")
+    }
+}
+
+impl KeyType {
+    /// Makes a type from the fields of a `type "NAME" { ... }` statement
+    pub fn from_fields(name: &str, fields: &[Field]) -> Result<Self> {
+        let mut key_type = KeyType {
+            name: name.to_owned(),
+            modifiers: Vec::new(),
+            map: Vec::new(),
+            preserve: Vec::new(),
+            level_names: Vec::new(),
+        };
+        let index = |field: &Field| field.index.as_ref()
+            .map(|i| modifiers(&Value::Expr(i.clone())))
+            .unwrap_or_else(|| Err(invalid(format!("missing index in type {:?}", name))));
+
+        for field in fields {
+            match field.name.as_deref().map(str::to_ascii_lowercase).as_deref() {
+                Some("modifiers") => key_type.modifiers = modifiers(&field.value)?,
+                Some("map") => {
+                    let level = match field.value {
+                        Value::Ident(ref l) => level(l),
+                        _ => None,
+                    };
+                    let level = level.ok_or_else(|| invalid(format!("invalid level in type {:?}", name)))?;
+                    key_type.map.push((index(field)?, level));
+                }
+                Some("preserve") => key_type.preserve.push((index(field)?, modifiers(&field.value)?)),
+                Some("level_name") | Some("levelname") => {
+                    let level = field.index.as_deref().and_then(level);
+                    match (level, &field.value) {
+                        (Some(level), Value::Str(s)) => key_type.level_names.push((level, s.clone())),
+                        _ => return Err(invalid(format!("invalid level name in type {:?}", name))),
+                    }
+                }
+                _ => (),
+            }
+        }
+
+        Ok(key_type)
+    }
+    /// Parses the types of every `xkb_types` section of a file
+    pub fn parse_all(src: &str) -> Result<Vec<Self>> {
+        let file = SymbolsFile::parse(src)?;
+        let mut types = Vec::new();
+        for section in file.sections.iter().filter(|s| s.kind == "xkb_types") {
+            for statement in &section.statements {
+                if let Statement::Type{name, fields} = statement {
+                    types.push(KeyType::from_fields(name, fields)?);
+                }
+            }
+        }
+        Ok(types)
+    }
+    /// The level, from 1, given while the named modifiers are held
+    pub fn level(&self, held: &[&str]) -> usize {
+        let held: Vec<&String> = self.modifiers.iter()
+            .filter(|m| held.iter().any(|h| m.eq_ignore_ascii_case(h)))
+            .collect();
+        self.map.iter()
+            .find(|(mods, _)| mods.len() == held.len() && mods.iter().all(|m| held.iter().any(|h| m.eq_ignore_ascii_case(h))))
+            .map_or(1, |&(_, level)| level)
+    }
+    /// The level given by the state of shift, caps lock and AltGr
+    pub fn level_for(&self, shift: bool, caps_lock: bool, altgr: bool) -> usize {
+        let mut held = Vec::with_capacity(4);
+        if shift {
+            held.push("Shift");
+        }
+        if caps_lock {
+            held.push("Lock");
+        }
+        if altgr {
+            held.extend_from_slice(&["LevelThree", "Mod5"]);
+        }
+        self.level(&held)
+    }
+}
+
+impl Display for KeyType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "    type \"{}\" {{", self.name)?;
+        writeln!(f, "        modifiers = {};", join(&self.modifiers))?;
+        for (mods, level) in &self.map {
+            writeln!(f, "        map[{}] = Level{};", join(mods), level)?;
+        }
+        for (mods, preserved) in &self.preserve {
+            writeln!(f, "        preserve[{}] = {};", join(mods), join(preserved))?;
+        }
+        for (level, name) in &self.level_names {
+            writeln!(f, "        level_name[Level{}] = {};", level, quote(name))?;
+        }
+        write!(f, "    }};")
+    }
+}
+
+lazy_static! {
+    static ref BUILTIN_TYPES: Vec<KeyType> = KeyType::parse_all(include_str!("types.xkb")).unwrap();
+}
+
+/// The standard types of xkeyboard-config klay assigns and understands
+pub fn builtin_types() -> &'static [KeyType] {
+    &BUILTIN_TYPES
+}
+
+/// Looks a type up in custom types and then in the built-in ones
+pub fn find_type<'a>(name: &str, custom: &'a [KeyType]) -> Option<&'a KeyType> {
+    custom.iter().chain(builtin_types()).find(|t| t.name == name)
+}
+
+fn upper(c: char) -> String {
+    c.to_uppercase().collect()
+}
+fn lower(c: char) -> String {
+    c.to_lowercase().collect()
+}
+fn is_lower(c: &Character) -> bool {
+    match *c {
+        Character::Char(c) => c.is_lowercase() && upper(c) != c.to_string(),
+        _ => false,
+    }
+}
+fn is_upper(c: &Character) -> bool {
+    match *c {
+        Character::Char(c) => c.is_uppercase() && lower(c) != c.to_string(),
+        _ => false,
+    }
+}
+fn is_keypad(c: &Character) -> bool {
+    match c {
+        Character::Symbol(s) => s.starts_with("KP_"),
+        _ => false,
+    }
+}
+/// Whether two levels are the lower and upper case of one letter
+fn is_pair(a: &Character, b: &Character) -> bool {
+    match (a, b) {
+        (&Character::Char(a), &Character::Char(b)) => is_lower(&Character::Char(a)) && is_upper(&Character::Char(b))
+            && (upper(a) == b.to_string() || lower(b) == a.to_string()),
+        _ => false,
+    }
+}
+
+fn pick_type(output: &Output, letters: fn(&Character, &Character) -> bool) -> &'static str {
+    let four = !(output.altgr.is_zero() && output.altgr_shift.is_zero());
+    let alpha = letters(&output.normal, &output.shift);
+    let keypad = is_keypad(&output.normal) || is_keypad(&output.shift);
+
+    match (four, alpha, keypad) {
+        (false, true, _) => "ALPHABETIC",
+        (false, false, true) => "KEYPAD",
+        (false, false, false) => "TWO_LEVEL",
+        (true, true, _) if letters(&output.altgr, &output.altgr_shift) => "FOUR_LEVEL_ALPHABETIC",
+        (true, true, _) => "FOUR_LEVEL_SEMIALPHABETIC",
+        (true, false, true) => "FOUR_LEVEL_KEYPAD",
+        (true, false, false) => "FOUR_LEVEL",
+    }
+}
+
+/// The type XKB gives a key written without one
+///
+/// XKB only checks that the levels are a lower and an upper case letter, not that they're the same letter.
+pub fn automatic_type(output: &Output) -> &'static str {
+    pick_type(output, |a, b| is_lower(a) && is_upper(b))
+}
+
+/// The type that makes caps lock affect only the levels that are the two cases of one letter
+pub fn suggested_type(output: &Output) -> &'static str {
+    pick_type(output, is_pair)
+}
@@ -9,12 +9,84 @@ use xml::writer::Result as WResult;
 use xml::writer::events::XmlEvent;
 use xml::common::XmlVersion;
 
+use crate::KeyboardKey;
+
+macro_rules! keyboard_key {
+    ($($code:expr => $kk:ident,)*) => {
+        /// The generic key with the given (ANSI) virtual key code
+        pub fn key_code_to_key(code: u16) -> Option<KeyboardKey> {
+            match code {
+                $( $code => Some(KeyboardKey::$kk), )*
+                _ => None,
+            }
+        }
+        pub fn key_to_key_code(k: KeyboardKey) -> u16 {
+            match k {
+                $( KeyboardKey::$kk => $code, )*
+            }
+        }
+    };
+}
+
+keyboard_key! {
+    50 => TLD,
+    18 => E01,
+    19 => E02,
+    20 => E03,
+    21 => E04,
+    23 => E05,
+    22 => E06,
+    26 => E07,
+    28 => E08,
+    25 => E09,
+    29 => E10,
+    27 => E11,
+    24 => E12,
+    12 => D01,
+    13 => D02,
+    14 => D03,
+    15 => D04,
+    17 => D05,
+    16 => D06,
+    32 => D07,
+    34 => D08,
+    31 => D09,
+    35 => D10,
+    33 => D11,
+    30 => D12,
+    0 => C01,
+    1 => C02,
+    2 => C03,
+    3 => C04,
+    5 => C05,
+    4 => C06,
+    38 => C07,
+    40 => C08,
+    37 => C09,
+    41 => C10,
+    39 => C11,
+    42 => BKS,
+    10 => LGT,
+    6 => B01,
+    7 => B02,
+    8 => B03,
+    9 => B04,
+    11 => B05,
+    45 => B06,
+    46 => B07,
+    43 => B08,
+    47 => B09,
+    44 => B10,
+    49 => SPC,
+    65 => KPD,
+}
+
 fn des<'de, T: FromStr, D: Deserializer<'de>>(d: D) -> Result<T, D::Error>
 where <T as FromStr>::Err: Debug {
     String::deserialize(d).map(|s| s.parse().unwrap())
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct KeyLayout {
     #[serde(deserialize_with = "des")]
     pub group: u16,
@@ -30,11 +102,11 @@ pub struct KeyLayout {
     pub terminators: Terminators,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct Layouts {
     pub layout: Vec<Layout>,
 }
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct Layout {
     #[serde(deserialize_with = "des")]
     pub first: u16,
@@ -44,7 +116,7 @@ pub struct Layout {
     pub map_set: String,
     pub modifiers: String,
 }
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct ModifierMap {
     pub id: String,
     #[serde(rename = "defaultIndex")]
@@ -53,33 +125,33 @@ pub struct ModifierMap {
     #[serde(rename = "keyMapSelect")]
     pub key_map_select: Vec<KeyMapSelect>,
 }
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct KeyMapSelect {
     #[serde(rename = "mapIndex")]
     #[serde(deserialize_with = "des")]
     pub map_index: u16,
     pub modifier: Vec<Modifier>,
 }
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct Modifier {
     pub keys: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct KeyMapSet {
     pub id: String,
     #[serde(rename = "keyMap")]
     pub key_map: Vec<KeyMap>
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct KeyMap {
     #[serde(deserialize_with = "des")]
     pub index: u16,
     pub key: Vec<Key>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 #[serde(untagged)]
 pub enum Key {
     Output {
@@ -94,23 +166,23 @@ pub enum Key {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct Actions {
     pub action: Vec<Action>
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct Terminators {
     pub when: Vec<When>
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 pub struct Action {
     pub id: String,
     pub when: Vec<When>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
 #[serde(untagged)]
 pub enum When {
     Output {
@@ -131,7 +203,7 @@ pub fn escape(s: &str) -> String {
     let mut out = String::with_capacity(s.len());
     for c in s.chars() {
         let codepoint = c as u32;
-        if codepoint >= 0x20 && codepoint <= 0x7e {
+        if (0x20..=0x7e).contains(&codepoint) && !"\"&'<>".contains(c) {
             out.push(c);
         } else {
             out.reserve(7);
@@ -156,13 +228,14 @@ pub fn write<W: Write>(w: W, keylayout: KeyLayout) -> WResult<()> {
     let mut writer = EmitterConfig{
         perform_escaping: false,
         perform_indent: true,
+        write_document_declaration: false,
         .. Default::default()
     }.indent_string("\t").create_writer(w);
     writer.write(XmlEvent::Comment("Created by LFalch's key-layout tool"))?;
     writer.write(XmlEvent::start_element("keyboard")
         .attr("group", &format!("{}", keylayout.group))
         .attr("id", &format!("{}", keylayout.id))
-        .attr("name", &keylayout.name)
+        .attr("name", &escape(&keylayout.name))
         // .attr("maxout", &format!("{}", keylayout.maxout))
     )?;
     writer.write(XmlEvent::start_element("layouts"))?;
@@ -170,15 +243,15 @@ pub fn write<W: Write>(w: W, keylayout: KeyLayout) -> WResult<()> {
         writer.write(XmlEvent::start_element("layout")
             .attr("first", &format!("{}", layout.first))
             .attr("last", &format!("{}", layout.last))
-            .attr("mapSet", &layout.map_set)
-            .attr("modifiers", &layout.modifiers)
+            .attr("mapSet", &escape(&layout.map_set))
+            .attr("modifiers", &escape(&layout.modifiers))
         )?;
         writer.write(XmlEvent::end_element())?;
     }
     writer.write(XmlEvent::end_element())?;
 
     writer.write(XmlEvent::start_element("modifierMap")
-        .attr("id", &keylayout.modifier_map.id)
+        .attr("id", &escape(&keylayout.modifier_map.id))
         .attr("defaultIndex", &format!("{}", keylayout.modifier_map.default_index))
     )?;
     for kms in &keylayout.modifier_map.key_map_select {
@@ -187,7 +260,7 @@ pub fn write<W: Write>(w: W, keylayout: KeyLayout) -> WResult<()> {
         )?;
         for modifier in &kms.modifier {
             writer.write(XmlEvent::start_element("modifier")
-                .attr("keys", &modifier.keys)
+                .attr("keys", &escape(&modifier.keys))
             )?;
             writer.write(XmlEvent::end_element())?;
         }
@@ -196,7 +269,7 @@ pub fn write<W: Write>(w: W, keylayout: KeyLayout) -> WResult<()> {
     writer.write(XmlEvent::end_element())?;
 
     writer.write(XmlEvent::start_element("keyMapSet")
-        .attr("id", &keylayout.key_map_set.id)
+        .attr("id", &escape(&keylayout.key_map_set.id))
     )?;
     for km in &keylayout.key_map_set.key_map {
         writer.write(XmlEvent::start_element("keyMap")
@@ -213,7 +286,7 @@ pub fn write<W: Write>(w: W, keylayout: KeyLayout) -> WResult<()> {
                 Key::Action{code, ref action} => {
                     writer.write(XmlEvent::start_element("key")
                         .attr("code", &format!("{}", code))
-                        .attr("action", action)
+                        .attr("action", &escape(action))
                     )?;
                 }
             }
@@ -226,20 +299,20 @@ pub fn write<W: Write>(w: W, keylayout: KeyLayout) -> WResult<()> {
     writer.write(XmlEvent::start_element("actions"))?;
     for action in &keylayout.actions.action {
         writer.write(XmlEvent::start_element("action")
-            .attr("id", &action.id)
+            .attr("id", &escape(&action.id))
         )?;
         for when in &action.when {
             match *when {
                 When::Output{ref state, ref output} => {
                     writer.write(XmlEvent::start_element("when")
-                        .attr("state", state)
+                        .attr("state", &escape(state))
                         .attr("output", &escape(output))
                     )?;
                 },
                 When::Next{ref state, ref next} => {
                     writer.write(XmlEvent::start_element("when")
-                        .attr("state", state)
-                        .attr("next", next)
+                        .attr("state", &escape(state))
+                        .attr("next", &escape(next))
                     )?;
                 }
             }
@@ -254,14 +327,14 @@ pub fn write<W: Write>(w: W, keylayout: KeyLayout) -> WResult<()> {
         match *when {
             When::Output{ref state, ref output} => {
                 writer.write(XmlEvent::start_element("when")
-                    .attr("state", state)
+                    .attr("state", &escape(state))
                     .attr("output", &escape(output))
                 )?;
             },
             When::Next{ref state, ref next} => {
                 writer.write(XmlEvent::start_element("when")
-                    .attr("state", state)
-                    .attr("next", next)
+                    .attr("state", &escape(state))
+                    .attr("next", &escape(next))
                 )?;
             }
         }
@@ -0,0 +1,278 @@
+use crate::{KlayLayout, KeyboardKey, Level, Out};
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use serde::{Serialize, Deserialize};
+
+/// Weights used to score how comfortable a layout is to type a corpus on
+///
+/// The cost of a corpus is the sum of the effort of every character typed
+/// plus a penalty for each bigram typed with the same finger on two different keys.
+/// Characters that can't be typed on the layout cost `missing` each.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CostModel {
+    #[serde(with = "crate::key_table")]
+    pub key_effort: BTreeMap<KeyboardKey, f64>,
+    #[serde(with = "crate::key_table")]
+    pub level_effort: BTreeMap<Level, f64>,
+    pub same_finger: f64,
+    pub missing: f64,
+}
+
+impl Default for CostModel {
+    fn default() -> Self {
+        use crate::KeyboardKey::*;
+
+        let key_effort = [
+            (TLD, 4.0), (E01, 3.5), (E02, 3.0), (E03, 3.0), (E04, 3.0), (E05, 3.5), (E06, 3.5),
+            (E07, 3.0), (E08, 3.0), (E09, 3.0), (E10, 3.5), (E11, 4.0), (E12, 4.5),
+            (D01, 2.0), (D02, 1.5), (D03, 1.5), (D04, 1.5), (D05, 2.0), (D06, 2.0),
+            (D07, 1.5), (D08, 1.5), (D09, 1.5), (D10, 2.0), (D11, 2.5), (D12, 3.0),
+            (C01, 1.0), (C02, 1.0), (C03, 1.0), (C04, 1.0), (C05, 1.5), (C06, 1.5),
+            (C07, 1.0), (C08, 1.0), (C09, 1.0), (C10, 1.0), (C11, 2.0), (BKS, 2.5),
+            (LGT, 3.0), (B01, 2.5), (B02, 2.0), (B03, 2.0), (B04, 2.0), (B05, 2.5),
+            (B06, 2.5), (B07, 2.0), (B08, 2.0), (B09, 2.0), (B10, 2.5),
+            (SPC, 0.5), (KPD, 5.0),
+        ].iter().copied().collect();
+        let level_effort = [
+            (Level::Normal, 0.),
+            (Level::Shift, 1.),
+            (Level::AltGr, 1.5),
+            (Level::AltGrShift, 2.5),
+        ].iter().copied().collect();
+
+        CostModel {
+            key_effort,
+            level_effort,
+            same_finger: 2.,
+            missing: 10.,
+        }
+    }
+}
+
+/// The finger a key is typed with when touch typing, counting from the left pinky (0)
+/// to the right pinky (7) with 8 being the thumbs
+pub fn finger(key: KeyboardKey) -> Option<u8> {
+    use crate::KeyboardKey::*;
+
+    Some(match key {
+        TLD | E01 | D01 | C01 | LGT | B01 => 0,
+        E02 | D02 | C02 | B02 => 1,
+        E03 | D03 | C03 | B03 => 2,
+        E04 | E05 | D04 | D05 | C04 | C05 | B04 | B05 => 3,
+        E06 | E07 | D06 | D07 | C06 | C07 | B06 | B07 => 4,
+        E08 | D08 | C08 | B08 => 5,
+        E09 | D09 | C09 | B09 => 6,
+        E10 | E11 | E12 | D10 | D11 | D12 | C10 | C11 | BKS | B10 => 7,
+        SPC => 8,
+        KPD => return None,
+    })
+}
+
+type Slot = (KeyboardKey, Level);
+
+/// Character and bigram frequencies of a corpus
+#[derive(Debug, Clone, Default)]
+pub struct Corpus {
+    chars: HashMap<char, u64>,
+    bigrams: HashMap<(char, char), u64>,
+}
+
+impl Corpus {
+    pub fn new(text: &str) -> Self {
+        let mut corpus = Corpus::default();
+        let mut last = None;
+
+        for c in text.chars() {
+            if c.is_control() {
+                last = None;
+                continue;
+            }
+            *corpus.chars.entry(c).or_insert(0) += 1;
+            if let Some(l) = last {
+                *corpus.bigrams.entry((l, c)).or_insert(0) += 1;
+            }
+            last = Some(c);
+        }
+
+        corpus
+    }
+}
+
+impl CostModel {
+    fn slot_cost(&self, (key, level): Slot) -> f64 {
+        self.key_effort.get(&key).copied().unwrap_or(0.)
+            + self.level_effort.get(&level).copied().unwrap_or(0.)
+    }
+    fn cost_with(&self, corpus: &Corpus, positions: &HashMap<char, Slot>) -> f64 {
+        let mut cost = 0.;
+
+        for (c, &n) in &corpus.chars {
+            cost += n as f64 * match positions.get(c) {
+                Some(&slot) => self.slot_cost(slot),
+                None => self.missing,
+            };
+        }
+        for ((a, b), &n) in &corpus.bigrams {
+            if let (Some(&(ka, _)), Some(&(kb, _))) = (positions.get(a), positions.get(b)) {
+                if ka != kb && finger(ka).is_some() && finger(ka) == finger(kb) {
+                    cost += n as f64 * self.same_finger;
+                }
+            }
+        }
+
+        cost
+    }
+    /// Scores how costly it is to type `corpus` on `layout` (lower is better)
+    pub fn cost(&self, layout: &KlayLayout, corpus: &Corpus) -> f64 {
+        self.cost_with(corpus, &self.positions(layout))
+    }
+
+    /// Finds the cheapest slot for every character on the layout
+    fn positions(&self, layout: &KlayLayout) -> HashMap<char, Slot> {
+        let mut positions = HashMap::new();
+
+        for (&key, outs) in &layout.keymap {
+            for &level in Level::ALL.iter() {
+                if let Out::Char(c) = *outs.get(level) {
+                    if c == '\0' {
+                        continue;
+                    }
+                    let slot = positions.entry(c).or_insert((key, level));
+                    if self.slot_cost((key, level)) < self.slot_cost(*slot) {
+                        *slot = (key, level);
+                    }
+                }
+            }
+        }
+
+        positions
+    }
+}
+
+/// A small xorshift generator so runs can be reproduced from a seed
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// Simulated annealing search over permutations of the characters on a layout
+///
+/// Every unpinned slot on the chosen keys may trade its output with any other such slot.
+#[derive(Debug, Clone)]
+pub struct Optimizer {
+    pub keys: BTreeSet<KeyboardKey>,
+    pub pinned_keys: BTreeSet<KeyboardKey>,
+    pub pinned_levels: BTreeSet<Level>,
+    pub pinned_slots: BTreeSet<(KeyboardKey, Level)>,
+    pub iterations: u64,
+    pub start_temperature: f64,
+    pub end_temperature: f64,
+    pub seed: u64,
+}
+
+impl Default for Optimizer {
+    fn default() -> Self {
+        Optimizer {
+            keys: BTreeSet::new(),
+            pinned_keys: BTreeSet::new(),
+            pinned_levels: BTreeSet::new(),
+            pinned_slots: BTreeSet::new(),
+            iterations: 100_000,
+            start_temperature: 10.,
+            end_temperature: 0.01,
+            seed: 0x2545_f491_4f6c_dd1d,
+        }
+    }
+}
+
+impl Optimizer {
+    /// Creates an optimizer that may move the characters on the given keys
+    pub fn new<I: IntoIterator<Item=KeyboardKey>>(keys: I) -> Self {
+        Optimizer {
+            keys: keys.into_iter().collect(),
+            .. Default::default()
+        }
+    }
+    pub fn pin_key(&mut self, key: KeyboardKey) -> &mut Self {
+        self.pinned_keys.insert(key);
+        self
+    }
+    pub fn pin_level(&mut self, level: Level) -> &mut Self {
+        self.pinned_levels.insert(level);
+        self
+    }
+    pub fn pin(&mut self, key: KeyboardKey, level: Level) -> &mut Self {
+        self.pinned_slots.insert((key, level));
+        self
+    }
+    fn free_slots(&self, layout: &KlayLayout) -> Vec<Slot> {
+        let mut slots = Vec::new();
+        for &key in &self.keys {
+            if self.pinned_keys.contains(&key) || !layout.keymap.contains_key(&key) {
+                continue;
+            }
+            for &level in Level::ALL.iter() {
+                if !self.pinned_levels.contains(&level) && !self.pinned_slots.contains(&(key, level)) {
+                    slots.push((key, level));
+                }
+            }
+        }
+        slots
+    }
+    /// Runs the search and returns the best layout found together with its cost
+    pub fn optimize(&self, layout: &KlayLayout, corpus: &Corpus, model: &CostModel) -> (KlayLayout, f64) {
+        let slots = self.free_slots(layout);
+        let mut current = layout.clone();
+        let mut current_cost = model.cost(&current, corpus);
+        let mut best = (current.clone(), current_cost);
+
+        if slots.len() < 2 {
+            return best;
+        }
+
+        let mut rng = Rng(self.seed | 1);
+        let cooling = (self.end_temperature / self.start_temperature).powf(1. / self.iterations.max(1) as f64);
+        let mut temperature = self.start_temperature;
+
+        for _ in 0..self.iterations {
+            let a = slots[rng.below(slots.len())];
+            let b = slots[rng.below(slots.len())];
+            if a != b {
+                swap(&mut current, a, b);
+                let cost = model.cost(&current, corpus);
+                let delta = cost - current_cost;
+
+                if delta <= 0. || rng.unit() < (-delta / temperature).exp() {
+                    current_cost = cost;
+                    if cost < best.1 {
+                        best = (current.clone(), cost);
+                    }
+                } else {
+                    swap(&mut current, a, b);
+                }
+            }
+            temperature *= cooling;
+        }
+
+        best
+    }
+}
+
+fn swap(layout: &mut KlayLayout, (ka, la): Slot, (kb, lb): Slot) {
+    let a = layout.keymap[&ka].get(la).clone();
+    let b = std::mem::replace(layout.keymap.get_mut(&kb).unwrap().get_mut(lb), a);
+    *layout.keymap.get_mut(&ka).unwrap().get_mut(la) = b;
+}
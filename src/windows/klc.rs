@@ -1,42 +1,91 @@
 use std::io::{Read, Write, Result};
+use std::fmt::{self, Display};
 
 use utf16_ext::{AutoWriter, AutoEndianLines, AutoEndianReader};
 use linked_hash_map::LinkedHashMap;
 
+use crate::KeyboardKey;
+
+use std::collections::BTreeMap;
+
 pub type ScanCode = u8;
 
-#[repr(u8)]
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
-pub enum CapsLockBehaviour {
-    Never = 0,
-    ShiftOnCaps = 1,
-    ShiftOnCapsAlt = 4,
-    ShiftOnCapsAlways = 5,
-    // Unsupported for now
-    // SgCap = "SGCap"
+/// The shift states of the layout columns when a file doesn't give them
+pub const DEFAULT_SHIFT_STATES: &[u8] = &[0, 1, 2, 6, 7];
+
+macro_rules! keyboard_key {
+    ($($code:expr => $kk:ident, $xkb:ident;)*) => {
+        /// The generic key with the given scan code
+        pub fn scan_code_to_key(sc: ScanCode) -> Option<KeyboardKey> {
+            match sc {
+                $( $code => Some(KeyboardKey::$kk), )*
+                _ => None,
+            }
+        }
+        pub fn key_to_scan_code(k: KeyboardKey) -> ScanCode {
+            match k {
+                $( KeyboardKey::$kk => $code, )*
+            }
+        }
+    };
+}
+
+scan_codes!(keyboard_key);
+
+/// What caps lock does to a key, as the flags of the `Cap` column
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct CapsLockBehaviour(pub u8);
+
+impl CapsLockBehaviour {
+    pub const NEVER: Self = CapsLockBehaviour(0);
+    /// Caps lock acts like Shift
+    pub const SHIFT_ON_CAPS: Self = CapsLockBehaviour(1);
+    /// Caps lock gives the characters of the key's `SGCap` line, written `SGCap`
+    pub const SG_CAP: Self = CapsLockBehaviour(2);
+    /// Caps lock acts like Shift with AltGr
+    pub const SHIFT_ON_CAPS_ALT: Self = CapsLockBehaviour(4);
+    pub const SHIFT_ON_CAPS_ALWAYS: Self = CapsLockBehaviour(5);
+    /// Kana lock acts on the key
+    pub const KANA_LOCK: Self = CapsLockBehaviour(8);
+
+    #[inline]
+    pub fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+    /// Reads the `Cap` column, `SGCap` or the flags as a number
+    pub fn parse(s: &str) -> Option<Self> {
+        if s.eq_ignore_ascii_case("SGCap") {
+            Some(Self::SG_CAP)
+        } else {
+            s.parse().ok().map(CapsLockBehaviour)
+        }
+    }
 }
 
 impl From<u8> for CapsLockBehaviour {
     fn from(n: u8) -> Self {
-        use self::CapsLockBehaviour::*;
-        match n {
-            0 => Never,
-            1 => ShiftOnCaps,
-            4 => ShiftOnCapsAlt,
-            5 => ShiftOnCapsAlways,
-            _ => panic!("No such behaviour")
-        }
+        CapsLockBehaviour(n)
     }
 }
 
 impl ::std::ops::BitOr for CapsLockBehaviour {
     type Output = Self;
     fn bitor(self, rhs: Self) -> Self {
-        (self as u8 | rhs as u8).into()
+        CapsLockBehaviour(self.0 | rhs.0)
     }
 }
 
-#[derive(Debug)]
+impl Display for CapsLockBehaviour {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if *self == Self::SG_CAP {
+            f.write_str("SGCap")
+        } else {
+            self.0.fmt(f)
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Key {
     pub virtual_key: String,
     /// Capslock Behaviour
@@ -51,9 +100,104 @@ pub struct Key {
     pub ctrl_alt: Option<char>,
     /// Shift + Ctrl + Alt (aka. Shift+AltGr) (shiftstate 7)
     pub shift_ctrl_alt: Option<char>,
+    /// The other shift states, e.g. Shift + Ctrl (shiftstate 3)
+    pub other_states: BTreeMap<u8, char>,
+    /// With [`CapsLockBehaviour::SG_CAP`], the characters of shift states while caps lock is on
+    pub sg_caps: BTreeMap<u8, char>,
+    /// The text of shift states giving several characters, written `%%` and in the LIGATURE table
+    pub ligatures: BTreeMap<u8, String>,
+}
+
+impl Key {
+    /// The character of a shift state, 1 being Shift, 2 Ctrl and 4 Alt
+    pub fn get(&self, state: u8) -> Option<char> {
+        match state {
+            0 => self.normal,
+            1 => self.shift,
+            2 => self.ctrl,
+            6 => self.ctrl_alt,
+            7 => self.shift_ctrl_alt,
+            _ => self.other_states.get(&state).copied(),
+        }
+    }
+    pub fn set(&mut self, state: u8, c: Option<char>) {
+        match state {
+            0 => self.normal = c,
+            1 => self.shift = c,
+            2 => self.ctrl = c,
+            6 => self.ctrl_alt = c,
+            7 => self.shift_ctrl_alt = c,
+            _ => match c {
+                Some(c) => {
+                    self.other_states.insert(state, c);
+                }
+                None => {
+                    self.other_states.remove(&state);
+                }
+            },
+        }
+    }
+}
+
+/// What a character typed after a dead key gives
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct DeadKeyResult {
+    pub c: char,
+    /// Whether the result is a dead key itself, written with an `@`
+    pub dead: bool,
+}
+
+impl From<char> for DeadKeyResult {
+    fn from(c: char) -> Self {
+        DeadKeyResult {
+            c,
+            dead: false,
+        }
+    }
+}
+
+/// An entry of the ATTRIBUTES table
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attribute {
+    /// Right Alt is AltGr, that is Ctrl + Alt
+    AltGr,
+    /// Caps lock locks Shift, and Shift releases it
+    ShiftLock,
+    /// Ctrl + Shift + 0 and Ctrl + Shift + 9 give the left-to-right and right-to-left marks
+    LrmRlm,
+    /// The Kana key locks like caps lock
+    KanaLock,
+    Other(String),
+}
+
+impl Attribute {
+    pub fn from_name(name: &str) -> Self {
+        match name {
+            "ALTGR" => Attribute::AltGr,
+            "SHIFTLOCK" => Attribute::ShiftLock,
+            "LRM_RLM" => Attribute::LrmRlm,
+            "KANALOK" => Attribute::KanaLock,
+            _ => Attribute::Other(name.to_owned()),
+        }
+    }
+    pub fn name(&self) -> &str {
+        match self {
+            Attribute::AltGr => "ALTGR",
+            Attribute::ShiftLock => "SHIFTLOCK",
+            Attribute::LrmRlm => "LRM_RLM",
+            Attribute::KanaLock => "KANALOK",
+            Attribute::Other(name) => name,
+        }
+    }
 }
 
-#[derive(Debug, Default)]
+impl Display for Attribute {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct WinKeyLayout {
     pub id: String,
     pub name: String,
@@ -62,8 +206,12 @@ pub struct WinKeyLayout {
     pub locale_name: String,
     pub locale_id: String,
     pub version: String,
+    /// The ATTRIBUTES table
+    pub attributes: Vec<Attribute>,
+    /// Shift states of the layout columns, [`DEFAULT_SHIFT_STATES`] when empty
+    pub shift_states: Vec<u8>,
     pub layout: LinkedHashMap<ScanCode, Key>,
-    pub deadkeys: LinkedHashMap<char, LinkedHashMap<char, char>>,
+    pub deadkeys: LinkedHashMap<char, LinkedHashMap<char, DeadKeyResult>>,
     pub key_names: LinkedHashMap<ScanCode, String>,
     pub key_names_ext: LinkedHashMap<u8, String>,
     pub keynames_dead: LinkedHashMap<char, String>,
@@ -76,6 +224,7 @@ enum Table {
     Attributes,
     ShiftState,
     Layout,
+    Ligature,
     Deadkey(char),
     Keyname,
     KeynameExt,
@@ -92,10 +241,18 @@ fn read_char(mut hex_codepoint: &str) -> Option<char> {
         hex_codepoint = &hex_codepoint[..hex_codepoint.len()-1];
     }
     hex_codepoint.parse().ok().or_else(|| {
-        u32::from_str_radix(hex_codepoint, 16).ok().and_then(|u| ::std::char::from_u32(u))
+        u32::from_str_radix(hex_codepoint, 16).ok().and_then(::std::char::from_u32)
     })
 }
 
+fn columns(shift_states: &[u8]) -> &[u8] {
+    if shift_states.is_empty() {
+        DEFAULT_SHIFT_STATES
+    } else {
+        shift_states
+    }
+}
+
 fn read_hb(hex_byte: &str) -> Option<u8> {
     u8::from_str_radix(hex_byte, 16).ok()
 }
@@ -111,6 +268,7 @@ fn st(s: &str) -> String {
 fn parse<R: Read>(lines: AutoEndianLines<R>) -> Option<WinKeyLayout> {
     let mut cur_table = Table::None;
     let mut ret = WinKeyLayout::default();
+    let mut last_scan_code = None;
 
     for line in lines {
         let line = line.unwrap();
@@ -126,6 +284,7 @@ fn parse<R: Read>(lines: AutoEndianLines<R>) -> Option<WinKeyLayout> {
             "ATTRIBUTES" => cur_table = Table::Attributes,
             "SHIFTSTATE" => cur_table = Table::ShiftState,
             "LAYOUT" => cur_table = Table::Layout,
+            "LIGATURE" => cur_table = Table::Ligature,
             "DEADKEY" => {
                 let c = read_char(args[1])?;
                 ret.deadkeys.insert(c, LinkedHashMap::new());
@@ -147,23 +306,64 @@ fn parse<R: Read>(lines: AutoEndianLines<R>) -> Option<WinKeyLayout> {
             "VERSION" => ret.version = args[1].to_owned(),
             "ENDKBD" => break,
             _ => match cur_table {
-                // HACK Ignore shift states and attributes
-                Table::Attributes => (),
-                Table::ShiftState => (),
+                Table::Attributes => ret.attributes.push(Attribute::from_name(args[0])),
+                Table::ShiftState => {
+                    // The column comments may be on the same line without a tab
+                    let state = args[0].split_whitespace().next()?;
+                    ret.shift_states.push(state.parse().ok()?);
+                }
+                // The caps lock line of an SGCap key has no scan code
+                Table::Layout if args[0] == "-1" => {
+                    let key = ret.layout.get_mut(&last_scan_code?)?;
+                    key.sg_caps.clear();
+                    for (i, &state) in columns(&ret.shift_states).iter().enumerate() {
+                        if let Some(c) = args.get(3 + i).and_then(|a| read_char(a)) {
+                            key.sg_caps.insert(state, c);
+                        }
+                    }
+                }
                 Table::Layout => {
-                    let key = Key {
-                        virtual_key: args[1].to_owned(),
-                        cap: args[2].parse::<u8>().ok()?.into(),
-                        normal: read_char(args[3]),
-                        shift: read_char(args[4]),
-                        ctrl: read_char(args[5]),
-                        ctrl_alt: read_char(args[6]),
-                        shift_ctrl_alt: read_char(args[7]),
+                    let mut key = Key {
+                        virtual_key: args.get(1)?.to_string(),
+                        cap: CapsLockBehaviour::parse(args.get(2)?)?,
+                        normal: None,
+                        shift: None,
+                        ctrl: None,
+                        ctrl_alt: None,
+                        shift_ctrl_alt: None,
+                        other_states: BTreeMap::new(),
+                        sg_caps: BTreeMap::new(),
+                        ligatures: BTreeMap::new(),
                     };
-                    ret.layout.insert(read_hb(args[0])?, key);
+                    // Columns follow the SHIFTSTATE table
+                    for (i, &state) in ret.columns().iter().enumerate() {
+                        key.set(state, args.get(3 + i).and_then(|a| read_char(a)));
+                    }
+                    let scan_code = read_hb(args[0])?;
+                    ret.layout.insert(scan_code, key);
+                    last_scan_code = Some(scan_code);
                 },
+                // The column is counted from the first one of the SHIFTSTATE table
+                Table::Ligature => {
+                    let state = *ret.columns().get(args.get(1)?.parse::<usize>().ok()?)?;
+                    // Characters outside the BMP are written as surrogate pairs
+                    let mut units = Vec::new();
+                    for a in &args[2..] {
+                        match a.parse::<char>() {
+                            Ok(c) => units.extend_from_slice(c.encode_utf16(&mut [0; 2])),
+                            Err(_) => units.push(u16::from_str_radix(a, 16).ok()?),
+                        }
+                    }
+                    let text = std::char::decode_utf16(units).map(|c| c.ok()).collect::<Option<String>>()?;
+                    let key = ret.layout.iter_mut().map(|(_, k)| k).find(|k| k.virtual_key == args[0])?;
+                    key.ligatures.insert(state, text);
+                }
                 Table::Deadkey(ref k) => {
-                    ret.deadkeys.get_mut(k)?.insert(read_char(args[0])?, read_char(args[1])?);
+                    let result = DeadKeyResult {
+                        c: read_char(args[1])?,
+                        dead: args[1].ends_with('@'),
+                    };
+                    ret.deadkeys.get_mut(k)?.insert(read_char(args[0])?, result);
                 }
                 Table::Keyname => {
                     ret.key_names.insert(read_hb(args[0])?, st(args[1]));
@@ -174,8 +374,9 @@ fn parse<R: Read>(lines: AutoEndianLines<R>) -> Option<WinKeyLayout> {
                 Table::KeynameDead => {
                     ret.keynames_dead.insert(read_char(args[0])?, st(args[1]));
                 }
-                Table::Descriptions => ret.description = args[1].to_owned(),
-                Table::LanguageNames => ret.language_name = args[1].to_owned(),
+                // Written with nothing after the language when empty
+                Table::Descriptions => ret.description = args.get(1).copied().unwrap_or_default().to_owned(),
+                Table::LanguageNames => ret.language_name = args.get(1).copied().unwrap_or_default().to_owned(),
                 Table::None => panic!("Unknown key `{}' with {:?}", args[0], &args[1..])
             }
         }
@@ -201,7 +402,28 @@ fn quote_multi(s: &str) -> String {
     }
 }
 
+fn shift_state_name(state: u8) -> &'static str {
+    match state {
+        0 => "Normal",
+        1 => "Shift",
+        2 => "Ctrl",
+        3 => "Shift Ctrl",
+        4 => "Alt",
+        5 => "Shift Alt",
+        6 => "AltGr",
+        7 => "Shift AltGr",
+        _ => "",
+    }
+}
+
 impl WinKeyLayout {
+    pub fn has_attribute(&self, attribute: &Attribute) -> bool {
+        self.attributes.contains(attribute)
+    }
+    /// The shift states of the layout columns
+    pub fn columns(&self) -> &[u8] {
+        columns(&self.shift_states)
+    }
     pub fn from_reader<R: Read>(reader: R) -> Option<Self> {
         let f = AutoEndianReader::new_auto_bom(reader).unwrap();
         parse(f.utf16_lines())
@@ -209,21 +431,23 @@ impl WinKeyLayout {
     pub fn write<W: Write>(&self, writer: W) -> Result<()> {
         // To make sure we don't forget anything we destructure it
         // This will make it so compilation fails if `WinKeyLayout` gains new fields
-        let &WinKeyLayout{
-            ref id,
-            ref name,
-            ref copyright,
-            ref company,
-            ref locale_name,
-            ref locale_id,
-            ref version,
-            ref layout,
-            ref deadkeys,
-            ref key_names,
-            ref key_names_ext,
-            ref keynames_dead,
-            ref description,
-            ref language_name,
+        let WinKeyLayout{
+            id,
+            name,
+            copyright,
+            company,
+            locale_name,
+            locale_id,
+            version,
+            attributes,
+            shift_states,
+            layout,
+            deadkeys,
+            key_names,
+            key_names_ext,
+            keynames_dead,
+            description,
+            language_name,
         } = self;
 
         let mut wr = AutoWriter::new_little(writer)?;
@@ -240,7 +464,25 @@ impl WinKeyLayout {
         wr.write_utf16_string("VERSION\t")?;
         wr.write_utf16_string(version)?;
         wr.write_utf16_string("\r\n\r\n")?;
-        wr.write_utf16_string("SHIFTSTATE\r\n\r\n0\r\n1 // Shift\r\n2 // Ctrl\r\n6 // AltGr\r\n7 // Shift AltGr\r\n\r\n")?;
+
+        if !attributes.is_empty() {
+            wr.write_utf16_string("ATTRIBUTES\r\n")?;
+            for attribute in attributes {
+                wr.write_utf16_string(attribute.name())?;
+                wr.write_utf16_string("\r\n")?;
+            }
+            wr.write_utf16_string("\r\n")?;
+        }
+
+        // Without the table the columns are the default ones, as when reading
+        let states = self.columns();
+        if !shift_states.is_empty() {
+            wr.write_utf16_string("SHIFTSTATE\r\n\r\n")?;
+            for &state in states {
+                wr.write_utf16_string(&format!("{}\t// {}\r\n", state, shift_state_name(state)))?;
+            }
+            wr.write_utf16_string("\r\n")?;
+        }
 
         wr.write_utf16_string("LAYOUT\t\t;an '@' indicates dead key\r\n\r\n")?;
         let k = |c: Option<char>| {
@@ -252,18 +494,54 @@ impl WinKeyLayout {
         };
 
         for (scancode, key) in layout {
-            let &Key{ref virtual_key, cap, normal, shift, ctrl, ctrl_alt, shift_ctrl_alt} = key;
-            let s = format!("{:02x}\t{}\t{}\t{}\t{}\t{}\t{}\t{}\r\n",
-                scancode, virtual_key, cap as u8, k(normal), k(shift), k(ctrl), k(ctrl_alt), k(shift_ctrl_alt));
+            let mut s = format!("{:02x}\t{}\t{}", scancode, key.virtual_key, key.cap);
+            for state in states {
+                s.push('\t');
+                if key.ligatures.contains_key(state) {
+                    s.push_str("%%");
+                } else {
+                    s.push_str(&k(key.get(*state)));
+                }
+            }
+            s.push_str("\r\n");
+            if key.cap.contains(CapsLockBehaviour::SG_CAP) {
+                s.push_str("-1\t-1\t0");
+                for state in states {
+                    s.push('\t');
+                    s.push_str(&k(key.sg_caps.get(state).copied()));
+                }
+                s.push_str("\r\n");
+            }
             wr.write_utf16_string(&s)?;
         }
         wr.write_utf16_string("\r\n\r\n")?;
 
+        if layout.values().any(|key| !key.ligatures.is_empty()) {
+            wr.write_utf16_string("LIGATURE\r\n\r\n")?;
+            for key in layout.values() {
+                for (column, state) in states.iter().enumerate() {
+                    if let Some(text) = key.ligatures.get(state) {
+                        let mut s = format!("{}\t{}", key.virtual_key, column);
+                        for unit in text.encode_utf16() {
+                            s.push_str(&format!("\t{:04x}", unit));
+                        }
+                        s.push_str(&format!("\t// {}\r\n", text));
+                        wr.write_utf16_string(&s)?;
+                    }
+                }
+            }
+            wr.write_utf16_string("\r\n")?;
+        }
+
         if !deadkeys.is_empty() {
             for (&deadkey, mappings) in deadkeys {
                 wr.write_utf16_string(&format!("DEADKEY\t{:04x}\r\n\r\n", deadkey as u32))?;
                 for (&a, &b) in mappings {
-                    let s = format!("{:04x}\t{:04x}\t// {} -> {}\r\n", a as u32, b as u32, a, b);
+                    // A result with a table of its own can only reach it as a dead key,
+                    // the dead key's own character typically ending it instead
+                    let chains = b.c != deadkey && deadkeys.contains_key(&b.c);
+                    let dead = if b.dead || chains { "@" } else { "" };
+                    let s = format!("{:04x}\t{:04x}{}\t// {} -> {}\r\n", a as u32, b.c as u32, dead, a, b.c);
                     wr.write_utf16_string(&s)?;
                 }
                 wr.write_utf16_string("\r\n")?;
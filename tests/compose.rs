@@ -0,0 +1,92 @@
+//! Compose sequences of Klay layouts are exported as far as each platform can express them
+
+use klay::KlayLayout;
+use klay::compose::{ComposeKey, InvalidSequence};
+use klay::emulate::{Model, Platform};
+
+use std::fs::read_to_string;
+
+fn layout() -> KlayLayout {
+    KlayLayout::from_str(&read_to_string(concat!(env!("CARGO_MANIFEST_DIR"), "/tests/data/test.klay.toml")).unwrap()).unwrap()
+}
+
+#[test]
+fn sequences() {
+    let sequences = layout().compose_sequences().unwrap();
+    let multi = sequences.iter().find(|s| s.output == "€").unwrap();
+    assert_eq!(multi.keys, [ComposeKey::Multi, ComposeKey::Char('e'), ComposeKey::Char('=')]);
+    let acute = sequences.iter().find(|s| s.output == "é").unwrap();
+    assert_eq!(acute.keys, [ComposeKey::Dead("acute".into(), '´'), ComposeKey::Char('e')]);
+}
+
+#[test]
+fn invalid() {
+    let mut layout = layout();
+    layout.compose.insert("<acute> <nokey>".to_owned(), "x".to_owned());
+    assert_eq!(layout.compose_sequences(), Err(InvalidSequence("<acute> <nokey>".to_owned())));
+    assert!(Model::from_klay(&layout, Platform::Windows).is_err());
+}
+
+#[cfg(feature = "linux")]
+#[test]
+fn xcompose() {
+    let (compose, unsupported) = klay::compose::to_xcompose(&layout().compose_sequences().unwrap());
+    assert!(compose.sequences.iter().any(|s| s.keys == ["Multi_key", "e", "equal"] && s.output == "€"));
+    assert!(compose.sequences.iter().any(|s| s.keys == ["dead_acute", "dead_acute", "e"] && s.output == "ế"));
+    assert_eq!(unsupported, []);
+}
+
+#[cfg(feature = "windows")]
+#[test]
+fn klc() {
+    use klay::klc::{DeadKeyResult, WinKeyLayout};
+
+    let (tables, unsupported) = klay::compose::to_klc(&layout().compose_sequences().unwrap());
+    assert_eq!(tables[&'´'][&'e'].c, 'é');
+    assert_eq!(tables[&'^'][&'´'].c, 'ˆ');
+    // Nothing gives a character for the first two keys to chain through
+    let reasons: Vec<_> = unsupported.iter().map(|u| u.reason).collect();
+    assert_eq!(reasons.len(), 2);
+    assert!(reasons.contains(&"Windows has no compose key"));
+    assert!(reasons.contains(&"chained dead keys need each prefix to output a character"));
+
+    // Once it does, the second dead key goes on from that character
+    let mut layout = layout();
+    layout.compose.insert("<acute> <acute>".to_owned(), "˝".to_owned());
+    let (tables, unsupported) = klay::compose::to_klc(&layout.compose_sequences().unwrap());
+    assert_eq!(tables[&'´'][&'´'], DeadKeyResult { c: '˝', dead: true });
+    assert_eq!(tables[&'˝'][&'e'], DeadKeyResult { c: 'ế', dead: false });
+    let reasons: Vec<_> = unsupported.iter().map(|u| (u.sequence.as_str(), u.reason)).collect();
+    assert!(reasons.contains(&("<acute> <acute> -> \"˝\"", "the output is a dead key chaining to longer sequences")), "{:?}", reasons);
+    assert_eq!(reasons.len(), 2);
+
+    // The chained result is written as a dead key
+    let klc = WinKeyLayout {
+        id: "chained".to_owned(),
+        name: "Chained".to_owned(),
+        version: "1.0".to_owned(),
+        deadkeys: tables,
+        ..WinKeyLayout::default()
+    };
+    let mut written = Vec::new();
+    klc.write(&mut written).unwrap();
+    let units: Vec<u16> = written[2..].chunks(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect();
+    let text = String::from_utf16(&units).unwrap();
+    assert!(text.contains("\r\n00b4\t02dd@\t// ´ -> ˝\r\n"), "{}", text);
+    assert!(text.contains("\r\nDEADKEY\t02dd\r\n\r\n0065\t1ebf\t// e -> ế\r\n"), "{}", text);
+    assert_eq!(WinKeyLayout::from_reader(&written[..]).unwrap().deadkeys, klc.deadkeys);
+}
+
+#[cfg(feature = "macos")]
+#[test]
+fn keylayout() {
+    use klay::keylayout::When;
+
+    let (actions, terminators, unsupported) = klay::compose::to_keylayout(&layout().compose_sequences().unwrap());
+    let e = actions.action.iter().find(|a| a.id == "e").unwrap();
+    assert!(e.when.contains(&When::Output{state: "acute".to_owned(), output: "é".to_owned()}));
+    assert!(e.when.contains(&When::Output{state: "acute acute".to_owned(), output: "ế".to_owned()}));
+    assert!(terminators.when.contains(&When::Output{state: "acute".to_owned(), output: "´".to_owned()}));
+    let reasons: Vec<_> = unsupported.iter().map(|u| u.reason).collect();
+    assert_eq!(reasons, ["macOS has no compose key"]);
+}
@@ -0,0 +1,39 @@
+//! Characters of dead keys are given the dead keysyms they stand for
+
+#![cfg(feature = "linux")]
+
+use klay::linux::{DEAD_KEYS, DEAD_KEY_ALIASES, DeadKeyMap, Keysym, dead_for};
+
+#[test]
+fn built_in() {
+    assert_eq!(dead_for('´'), Some("acute"));
+    assert_eq!(dead_for('¨'), Some("diaeresis"));
+    assert_eq!(dead_for('\u{301}'), Some("acute"));
+    assert_eq!(dead_for('°'), Some("abovering"));
+    assert_eq!(dead_for('a'), None);
+
+    for &(name, _) in DEAD_KEYS.iter() {
+        assert!(Keysym::from_name(&format!("dead_{}", name)).is_some_and(Keysym::is_dead), "{}", name);
+    }
+    for &(c, name) in DEAD_KEY_ALIASES.iter() {
+        assert!(DEAD_KEYS.iter().any(|&(n, _)| n == name), "{} ({:?})", name, c);
+    }
+}
+
+#[test]
+fn mapping_file() {
+    let map = DeadKeyMap::from_reader(&br"
+        # Overrides and additions
+        ' dead_abovecomma
+        U02bb  abovereversedcomma
+    "[..]).unwrap();
+
+    assert_eq!(map.get('\''), Some("abovecomma"));
+    assert_eq!(map.get('ʻ'), Some("abovereversedcomma"));
+    assert_eq!(map.get('´'), Some("acute"));
+    assert_eq!(map.get('x'), None);
+
+    for &line in &["x", "x acute extra", "xy acute", "U12345678 acute", "x dead_madeup", "x BackSpace"] {
+        assert!(DeadKeyMap::from_reader(line.as_bytes()).is_err(), "{}", line);
+    }
+}
@@ -0,0 +1,76 @@
+//! Dead keys that don't compose with the next key act as the platform they come from does
+
+use klay::{KeyboardKey, Level};
+use klay::emulate::{Action, DeadKey, Emulator, KeyActions, Modifiers, Platform, Model};
+
+use KeyboardKey::{B01, C01, C10, C11};
+
+fn model(platform: Platform) -> Model {
+    let mut model = Model::new(platform);
+    let key = |action: Action| {
+        let mut actions = KeyActions::default();
+        actions.levels.insert(Level::Normal, action);
+        actions
+    };
+    model.keys.insert(C01, key(Action::Text("a".to_owned())));
+    model.keys.insert(B01, key(Action::Text("x".to_owned())));
+    model.keys.insert(C10, key(Action::Dead("acute".to_owned())));
+    model.keys.insert(C11, key(Action::Dead("grave".to_owned())));
+    model.dead_keys.insert("grave".to_owned(), DeadKey {
+        terminator: "`".to_owned(),
+        ..DeadKey::default()
+    });
+    model.add_sequence("acute", "´".to_owned(), &['a'], "á");
+    // Acute and grave make a state of its own
+    model.add_sequence("acute", "´".to_owned(), &['`', 'a'], "ǻ");
+    model
+}
+
+fn type_keys(emulator: &mut Emulator, keys: &[KeyboardKey]) -> String {
+    emulator.type_keys(keys.iter().map(|&k| (k, Modifiers::default())))
+}
+
+#[test]
+fn composing() {
+    for &platform in [Platform::Windows, Platform::Linux, Platform::MacOs].iter() {
+        let model = model(platform);
+        let mut emulator = Emulator::new(&model);
+        assert_eq!(type_keys(&mut emulator, &[C10, C01]), "á", "{:?}", platform);
+        assert_eq!(type_keys(&mut emulator, &[C10, C11]), "", "{:?}", platform);
+        assert_eq!(emulator.dead_state(), Some("acute `"));
+        assert_eq!(type_keys(&mut emulator, &[C01]), "ǻ", "{:?}", platform);
+        assert_eq!(emulator.dead_state(), None);
+    }
+}
+
+#[test]
+fn windows() {
+    let model = model(Platform::Windows);
+    let mut emulator = Emulator::new(&model);
+    // The dead key's character comes before the other key's
+    assert_eq!(type_keys(&mut emulator, &[C11, B01]), "`x");
+    assert_eq!(type_keys(&mut emulator, &[C11, C10]), "`´");
+    assert_eq!(emulator.dead_state(), None);
+}
+
+#[test]
+fn linux() {
+    let model = model(Platform::Linux);
+    let mut emulator = Emulator::new(&model);
+    // The sequence is cancelled
+    assert_eq!(type_keys(&mut emulator, &[C11, B01]), "");
+    assert_eq!(type_keys(&mut emulator, &[C11, C10]), "");
+    assert_eq!(emulator.dead_state(), None);
+    assert_eq!(type_keys(&mut emulator, &[B01]), "x");
+}
+
+#[test]
+fn macos() {
+    let model = model(Platform::MacOs);
+    let mut emulator = Emulator::new(&model);
+    // The terminator is output and the other key acts on its own
+    assert_eq!(type_keys(&mut emulator, &[C11, B01]), "`x");
+    assert_eq!(type_keys(&mut emulator, &[C11, C10]), "`");
+    assert_eq!(emulator.dead_state(), Some("acute"));
+    assert_eq!(type_keys(&mut emulator, &[C01]), "á");
+}
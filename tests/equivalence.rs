@@ -0,0 +1,50 @@
+//! Layouts are equivalent when every key sequence types the same on both
+
+use klay::{KeyboardKey, Level};
+use klay::emulate::{Action, KeyActions, Modifiers, Platform, Model};
+use klay::equivalence::compare;
+
+use KeyboardKey::{C01, C10};
+
+fn model(platform: Platform, composed: &str) -> Model {
+    let mut model = Model::new(platform);
+    let mut a = KeyActions::default();
+    a.levels.insert(Level::Normal, Action::Text("a".to_owned()));
+    a.levels.insert(Level::Shift, Action::Text("A".to_owned()));
+    a.caps_shifts(Level::Normal, Level::Shift);
+    model.keys.insert(C01, a);
+    let mut acute = KeyActions::default();
+    acute.levels.insert(Level::Normal, Action::Dead("acute".to_owned()));
+    model.keys.insert(C10, acute);
+    model.add_sequence("acute", "´".to_owned(), &['a'], composed);
+    model
+}
+
+#[test]
+fn same() {
+    let left = model(Platform::Windows, "á");
+    assert_eq!(compare(&left, &left.clone()), []);
+}
+
+#[test]
+fn dead_keys() {
+    let differences = compare(&model(Platform::Windows, "á"), &model(Platform::Windows, "à"));
+    assert_eq!(differences.len(), 2, "{:?}", differences);
+
+    let difference = &differences[0];
+    assert_eq!(difference.keys, [(C10, Modifiers::default()), (C01, Modifiers::default())]);
+    assert_eq!((&*difference.left, &*difference.right), ("á", "à"));
+    assert_eq!(difference.to_string(), "c10, c01: \"á\" vs \"à\"");
+    // With caps lock it's Shift that types 'a'
+    let caps = Modifiers { caps_lock: true, ..Modifiers::default() };
+    assert_eq!(differences[1].keys[1], (C01, Modifiers { shift: true, ..caps }));
+}
+
+#[test]
+fn platforms() {
+    // A dead key followed by one it doesn't compose with only differs
+    // where the platforms disagree on it
+    let differences = compare(&model(Platform::Windows, "á"), &model(Platform::Linux, "á"));
+    assert!(!differences.is_empty());
+    assert!(differences.iter().all(|d| d.keys.len() == 2 && d.right.is_empty()), "{:?}", differences);
+}
@@ -0,0 +1,52 @@
+//! Keysym names are read back as the characters they were written for
+
+#![cfg(feature = "linux")]
+
+use klay::linux::{KeysymPolicy, NAMES, char_to_keysym, char_to_name, name_to_char};
+
+#[test]
+fn whole_table() {
+    let policies = [KeysymPolicy::Canonical, KeysymPolicy::Unicode, KeysymPolicy::NoDeprecated];
+    for (name, _, c) in NAMES.iter() {
+        assert_eq!(name_to_char(name), c, "{}", name);
+        let c = match c {
+            Some(c) => c,
+            None => continue,
+        };
+        assert_eq!(name_to_char(&char_to_name(c)), Some(c), "{} ({})", name, char_to_name(c));
+        // XKB has no keysyms for control characters
+        if c.is_control() && c != '\0' {
+            continue;
+        }
+        for &policy in &policies {
+            assert_eq!(name_to_char(&char_to_keysym(c, policy)), Some(c), "{} ({:?})", name, policy);
+        }
+    }
+}
+
+#[test]
+fn unnamed() {
+    for &c in &['ẞ', '☃', '🦀', 'ǅ'] {
+        assert_eq!(name_to_char(&char_to_name(c)), Some(c));
+    }
+}
+
+#[test]
+fn forms() {
+    assert_eq!(name_to_char("Tab"), None);
+    assert_eq!(name_to_char("BackSpace"), None);
+    assert_eq!(name_to_char("eacute"), Some('é'));
+    assert_eq!(name_to_char("U20ac"), Some('€'));
+    assert_eq!(name_to_char("U20AC"), Some('€'));
+    assert_eq!(name_to_char("U00e9"), Some('é'));
+    assert_eq!(name_to_char("0x10020ac"), Some('€'));
+    assert_eq!(name_to_char("0x20ac"), Some('€'));
+    assert_eq!(name_to_char("0xe9"), Some('é'));
+    assert_eq!(name_to_char("233"), Some('é'));
+    assert_eq!(name_to_char("7"), Some('7'));
+    assert_eq!(name_to_char("U"), Some('U'));
+
+    for &name in &["", "U+20AC", "U-1", "U0008", "U110000", "Uvwxyz", "0x", "0x+1", "0xff08", "not_a_keysym"] {
+        assert_eq!(name_to_char(name), None, "{}", name);
+    }
+}
@@ -0,0 +1,41 @@
+//! Characters are named as asked when written as keysyms
+
+#![cfg(feature = "linux")]
+
+use klay::linux::{Layout, KeysymPolicy, char_to_keysym, NAMES};
+
+use std::fs::File;
+
+#[test]
+fn policies() {
+    use KeysymPolicy::*;
+
+    assert_eq!(char_to_keysym('\'', Canonical), "apostrophe");
+    assert_eq!(char_to_keysym('\'', Unicode), "U0027");
+    assert_eq!(char_to_keysym('\'', NoDeprecated), "apostrophe");
+    assert_eq!(char_to_keysym('ẞ', Canonical), "U1e9e");
+
+    // A legacy name is left for another one
+    assert!(NAMES.is_deprecated("horizconnector"));
+    assert_eq!(char_to_keysym('─', NoDeprecated), "horizlinescan5");
+    // The only name of the character is a legacy one
+    assert_eq!(char_to_keysym('☆', Canonical), "openstar");
+    assert!(NAMES.is_deprecated("openstar"));
+    assert_eq!(char_to_keysym('☆', NoDeprecated), "U2606");
+
+    for &policy in &[Canonical, Unicode, NoDeprecated] {
+        assert_eq!(char_to_keysym('\0', policy), "NoSymbol");
+    }
+}
+
+#[test]
+fn write_unicode() {
+    let layout = Layout::from_reader(File::open("tests/data/test.xkb").unwrap()).unwrap();
+
+    let mut written = Vec::new();
+    layout.write_with_keysyms(&mut written, KeysymPolicy::Unicode).unwrap();
+    let written = String::from_utf8(written).unwrap();
+    assert!(written.contains("U0071,      U0051"));
+    assert!(!written.contains("aring"));
+    assert_eq!(Layout::from_reader(written.as_bytes()).unwrap(), layout);
+}
@@ -0,0 +1,73 @@
+//! Keysyms that aren't characters have values and names too
+
+#![cfg(feature = "linux")]
+
+use klay::linux::{Character, Keysym, NAMES};
+
+#[test]
+fn named() {
+    let keysyms = [
+        ("BackSpace", 0xff08),
+        ("Return", 0xff0d),
+        ("ISO_Level3_Shift", 0xfe03),
+        ("Multi_key", 0xff20),
+        ("dead_acute", 0xfe51),
+        ("XF86AudioMute", 0x1008ff12),
+        ("NoSymbol", 0),
+    ];
+    for &(name, value) in &keysyms {
+        let keysym = Keysym::from_name(name).unwrap();
+        assert_eq!(keysym, Keysym(value));
+        assert_eq!(keysym.name(), Some(name));
+        assert_eq!(keysym.to_string(), name);
+    }
+    assert_eq!(Keysym::from_name("BackSpace").unwrap().to_char(), None);
+    assert!(Keysym(0xfe51).is_dead());
+    assert!(!Keysym(0xff08).is_dead());
+    assert_eq!(Keysym::from_name("Made_up"), None);
+}
+
+#[test]
+fn characters() {
+    assert_eq!(Keysym::from_char('a'), Keysym(0x61));
+    assert_eq!(Keysym::from_char('€'), Keysym(0x20ac));
+    assert_eq!(Keysym::from_char('ẞ'), Keysym(0x1001e9e));
+    assert_eq!(Keysym::from_char('\0'), Keysym::NO_SYMBOL);
+
+    for &c in &['a', 'é', '€', 'ẞ', '☃'] {
+        assert_eq!(Keysym::from_char(c).to_char(), Some(c));
+    }
+    assert_eq!(Keysym(0x1001e9e).to_string(), "U1e9e");
+    // Neither named nor a character
+    assert_eq!(Keysym(0x1234_5678).to_char(), None);
+    assert_eq!(Keysym(0x1234_5678).to_string(), "0x12345678");
+}
+
+#[test]
+fn in_characters() {
+    let pairs = [
+        (Keysym(0xff08), Character::Symbol("BackSpace".into())),
+        (Keysym(0xfe51), Character::Dead("acute".into())),
+        (Keysym(0xe9), Character::Char('é')),
+        (Keysym(0x1001e9e), Character::Char('ẞ')),
+        (Keysym::NO_SYMBOL, Character::default()),
+    ];
+    for (keysym, character) in pairs.iter().cloned() {
+        assert_eq!(Character::from(keysym), character);
+        assert_eq!(character.to_keysym(), Some(keysym));
+    }
+    assert_eq!(Character::from(Keysym(0x1234_5678)), Character::Symbol("0x12345678".into()));
+}
+
+#[test]
+fn tables() {
+    // Keysyms with several names are known by the first one
+    assert_eq!(NAMES.get_keysym("script_switch"), Some(0xff7e));
+    assert_eq!(NAMES.get_keysym_name(0xff7e), Some("Mode_switch"));
+    assert_eq!(NAMES.get_keysym_char(0x27), Some('\''));
+    assert_eq!(NAMES.get_char("quoteright"), Some('\''));
+    assert_eq!(NAMES.get_name('\''), Some("apostrophe"));
+    assert_eq!(NAMES.get_char("BackSpace"), None);
+    assert_eq!(NAMES.get_keysym(""), None);
+    assert_eq!(NAMES.get_keysym_name(0x1234_5678), None);
+}
@@ -0,0 +1,245 @@
+//! KLC files are read and written with the tables they have
+
+#![cfg(feature = "windows")]
+
+use klay::klc::{Attribute, WinKeyLayout};
+
+/// A KLC file as Windows tools save it, in UTF-16 with a byte order mark
+fn klc(src: &str) -> Vec<u8> {
+    let mut bytes = vec![0xff, 0xfe];
+    for unit in src.replace('\n', "\r\n").encode_utf16() {
+        bytes.extend_from_slice(&unit.to_le_bytes());
+    }
+    bytes
+}
+
+fn read(bytes: &[u8]) -> WinKeyLayout {
+    WinKeyLayout::from_reader(bytes).unwrap()
+}
+
+fn write(layout: &WinKeyLayout) -> Vec<u8> {
+    let mut written = Vec::new();
+    layout.write(&mut written).unwrap();
+    written
+}
+
+fn text(bytes: &[u8]) -> String {
+    let units: Vec<u16> = bytes[2..].chunks(2).map(|b| u16::from_le_bytes([b[0], b[1]])).collect();
+    String::from_utf16(&units).unwrap()
+}
+
+const SHIFT_STATES: &str = "KBD\tstates\t\"Shift states\"
+
+VERSION\t1.0
+
+SHIFTSTATE
+
+0\t//Column 4
+1\t//Column 5 : Shft
+3\t//Column 6 : Shft  Ctrl
+6\t//Column 7 :       Ctrl Alt
+
+LAYOUT
+
+10\tQ\t1\tq\tQ\t0011\t0040\t// q, Q, , @
+11\tW\t1\tw\tW\t-1\t-1
+
+ENDKBD
+";
+
+#[test]
+fn shift_states() {
+    let layout = read(&klc(SHIFT_STATES));
+    assert_eq!(layout.shift_states, [0, 1, 3, 6]);
+    assert_eq!(layout.columns(), [0, 1, 3, 6]);
+
+    let q = &layout.layout[&0x10];
+    assert_eq!(q.normal, Some('q'));
+    assert_eq!(q.shift, Some('Q'));
+    assert_eq!(q.ctrl, None);
+    assert_eq!(q.get(3), Some('\u{11}'));
+    assert_eq!(q.ctrl_alt, Some('@'));
+    assert_eq!(q.shift_ctrl_alt, None);
+    assert_eq!(layout.layout[&0x11].get(3), None);
+
+    // The columns are written back in the same order
+    let written = write(&layout);
+    let text = text(&written);
+    assert!(text.contains("SHIFTSTATE\r\n\r\n0\t// Normal\r\n1\t// Shift\r\n3\t// Shift Ctrl\r\n6\t// AltGr\r\n\r\n"));
+    assert!(text.contains("10\tQ\t1\t0071\t0051\t0011\t0040\r\n"));
+    assert_eq!(read(&written), layout);
+}
+
+#[test]
+fn default_shift_states() {
+    let layout = read(&klc("KBD\tdefault\t\"Default\"\n\nLAYOUT\n\n10\tQ\t1\tq\tQ\t0011\t0040\t-1\n\nENDKBD\n"));
+    assert!(layout.shift_states.is_empty());
+    assert_eq!(layout.columns(), [0, 1, 2, 6, 7]);
+    assert_eq!(layout.layout[&0x10].ctrl, Some('\u{11}'));
+    assert_eq!(layout.layout[&0x10].ctrl_alt, Some('@'));
+}
+
+const SG_CAP: &str = "KBD\tsgcap\t\"SGCap\"
+
+VERSION\t1.0
+
+SHIFTSTATE
+
+0
+1
+2
+
+LAYOUT
+
+02\t1\tSGCap\t0031\t0021\t-1
+-1\t-1\t0\t00b9\t00a1
+03\t2\t3\t0032\t0040\t-1
+-1\t-1\t0\t00b2\t-1\t-1
+04\t3\t8\t0033\t0023\t-1
+05\t4\t5\t0034\t0024\t-1
+
+ENDKBD
+";
+
+#[test]
+fn sg_cap() {
+    use klay::klc::CapsLockBehaviour;
+
+    let layout = read(&klc(SG_CAP));
+    let one = &layout.layout[&0x02];
+    assert_eq!(one.cap, CapsLockBehaviour::SG_CAP);
+    assert_eq!(one.sg_caps.get(&0), Some(&'¹'));
+    assert_eq!(one.sg_caps.get(&1), Some(&'¡'));
+    assert_eq!(one.sg_caps.get(&2), None);
+    // Other flags combine with it
+    let two = &layout.layout[&0x03];
+    assert!(two.cap.contains(CapsLockBehaviour::SG_CAP) && two.cap.contains(CapsLockBehaviour::SHIFT_ON_CAPS));
+    assert_eq!(two.sg_caps.len(), 1);
+    assert_eq!(layout.layout[&0x04].cap, CapsLockBehaviour::KANA_LOCK);
+    assert_eq!(layout.layout[&0x05].cap, CapsLockBehaviour::SHIFT_ON_CAPS_ALWAYS);
+    assert!(layout.layout[&0x05].sg_caps.is_empty());
+
+    let written = write(&layout);
+    let text = text(&written);
+    assert!(text.contains("02\t1\tSGCap\t0031\t0021\t-1\r\n-1\t-1\t0\t00b9\t00a1\t-1\r\n03\t2\t3\t"));
+    assert!(text.contains("04\t3\t8\t0033\t0023\t-1\r\n05\t4\t5\t"));
+    assert_eq!(read(&written), layout);
+}
+
+#[test]
+fn sg_cap_emulated() {
+    use klay::Level;
+    use klay::emulate::{Action, Model};
+
+    let model = Model::from_klc(&read(&klc(SG_CAP)));
+    let one = &model.keys[&klay::KeyboardKey::E01];
+    assert_eq!(one.get(Level::Normal, false), Some(&Action::Text("1".to_owned())));
+    assert_eq!(one.get(Level::Normal, true), Some(&Action::Text("¹".to_owned())));
+    assert_eq!(one.get(Level::Shift, true), Some(&Action::Text("¡".to_owned())));
+}
+
+const LIGATURES: &str = "KBD\tlig\t\"Ligatures\"
+
+VERSION\t1.0
+
+SHIFTSTATE
+
+0
+1
+6
+
+LAYOUT
+
+10\tQ\t1\tq\t%%\t%%
+11\tW\t1\tw\tW\t-1
+
+LIGATURE
+
+//VK_\tMod#\tChar0\tChar1\tChar2\tChar3
+//----\t\t----\t----\t----\t----
+
+Q\t1\t0051\t0301\t\t// Q́
+Q\t2\t0071\t0323\t0301
+
+ENDKBD
+";
+
+#[test]
+fn ligatures() {
+    let layout = read(&klc(LIGATURES));
+    let q = &layout.layout[&0x10];
+    assert_eq!(q.normal, Some('q'));
+    assert_eq!(q.shift, None);
+    assert_eq!(q.ligatures.get(&1).map(String::as_str), Some("Q\u{301}"));
+    // The column is the third one, shift state 6
+    assert_eq!(q.ligatures.get(&6).map(String::as_str), Some("q\u{323}\u{301}"));
+    assert!(layout.layout[&0x11].ligatures.is_empty());
+
+    let written = write(&layout);
+    let text = text(&written);
+    assert!(text.contains("10\tQ\t1\t0071\t%%\t%%\r\n"));
+    assert!(text.contains("LIGATURE\r\n\r\nQ\t1\t0051\t0301\t// Q\u{301}\r\nQ\t2\t0071\t0323\t0301\t// q\u{323}\u{301}\r\n"));
+    assert_eq!(read(&written), layout);
+
+    let model = klay::emulate::Model::from_klc(&layout);
+    let q = &model.keys[&klay::KeyboardKey::D01];
+    assert_eq!(q.get(klay::Level::AltGr, false), Some(&klay::emulate::Action::Text("q\u{323}\u{301}".to_owned())));
+}
+
+#[test]
+fn attributes() {
+    let layout = read(&klc("KBD\tattrs\t\"Attributes\"\n\nVERSION\t1.0\n\nATTRIBUTES\nALTGR\nSHIFTLOCK\nLRM_RLM\nKANALOK\nFUTURE\n\nENDKBD\n"));
+    assert_eq!(layout.attributes, [
+        Attribute::AltGr,
+        Attribute::ShiftLock,
+        Attribute::LrmRlm,
+        Attribute::KanaLock,
+        Attribute::Other("FUTURE".to_owned()),
+    ]);
+    assert!(layout.has_attribute(&Attribute::ShiftLock));
+
+    let written = write(&layout);
+    assert!(text(&written).contains("ATTRIBUTES\r\nALTGR\r\nSHIFTLOCK\r\nLRM_RLM\r\nKANALOK\r\nFUTURE\r\n"));
+    assert_eq!(read(&written), layout);
+}
+
+#[test]
+fn non_bmp_ligature() {
+    let layout = read(&klc("KBD\tlig\t\"Ligatures\"\n\nVERSION\t1.0\n\nLAYOUT\n\n11\tW\t1\t%%\tW\t-1\t-1\t-1\n\nLIGATURE\n\nW\t0\td835\tdc00\t0301\n\nENDKBD\n"));
+    assert_eq!(layout.layout[&0x11].ligatures.get(&0).map(String::as_str), Some("\u{1d400}\u{301}"));
+
+    let written = write(&layout);
+    assert!(text(&written).contains("W\t0\td835\tdc00\t0301\t// \u{1d400}\u{301}\r\n"));
+    assert_eq!(read(&written), layout);
+}
+
+#[test]
+fn chained_dead_keys() {
+    let mut layout = read(&klc("KBD\tdead\t\"Dead\"\n\nVERSION\t1.0\n\nDEADKEY\t00b4\n\n00b4\t02dd\n0020\t00b4\n\nDEADKEY\t02dd\n\n006f\t0151\n0020\t02dd\n\nENDKBD\n"));
+    // Results without a mark are kept as they are
+    assert!(!layout.deadkeys[&'´'][&'´'].dead);
+
+    // but written with one when they have a table to chain on to
+    let written = write(&layout);
+    let text = text(&written);
+    assert!(text.contains("00b4\t02dd@\t"));
+    assert!(text.contains("0020\t00b4\t"));
+    assert!(text.contains("0020\t02dd\t"));
+    layout.deadkeys.get_mut(&'´').unwrap().get_mut(&'´').unwrap().dead = true;
+    assert_eq!(read(&written), layout);
+}
+
+#[test]
+fn chained_dead_keys_emulated() {
+    use klay::KeyboardKey::{D09, E12};
+    use klay::emulate::{Action, Emulator, Model, Modifiers};
+
+    let model = Model::from_klc(&read(&klc("KBD\tdead\t\"Dead\"\n\nVERSION\t1.0\n\nSHIFTSTATE\n\n0\n1\n\nLAYOUT\n\n0d\tOEM_PLUS\t0\t00b4@\t-1\n18\tO\t1\t006f\t004f\n\nDEADKEY\t00b4\n\n00b4\t02dd@\n006f\t00f3\n0020\t00b4\n\nDEADKEY\t02dd\n\n006f\t0151\n0020\t02dd\n\nENDKBD\n")));
+    assert_eq!(model.dead_keys["´"].compose[&'´'], Action::Dead("˝".to_owned()));
+    // The dead key's own character ends it rather than chaining
+    assert_eq!(model.dead_keys["´"].compose[&' '], Action::Text("´".to_owned()));
+
+    let mut emulator = Emulator::new(&model);
+    assert_eq!(emulator.type_keys([E12, D09].iter().map(|&k| (k, Modifiers::default()))), "ó");
+    assert_eq!(emulator.type_keys([E12, E12, D09].iter().map(|&k| (k, Modifiers::default()))), "ő");
+}
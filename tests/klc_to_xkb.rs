@@ -0,0 +1,105 @@
+//! KLC layouts convert to XKB symbols against a base, with what's lost reported
+
+#![cfg(all(feature = "windows", feature = "linux"))]
+
+use klay::klc::{Attribute, WinKeyLayout};
+use klay::convert::{Base, KlcToXkb, Warning, linux_to_win, try_win_to_linux};
+use klay::linux::{Character, Key, Resolver, SymbolsFile};
+
+use std::fs::File;
+
+const DK: &str = r#"
+default partial alphanumeric_keys
+xkb_symbols "basic" {
+    key <AE01> { [ 1, exclam, exclamdown, onesuperior ] };
+    key <AE02> { [ 2, quotedbl, at, oneeighth ] };
+    key <TLDE> { [ onehalf, section, threequarters, paragraph ] };
+};
+"#;
+
+fn layout() -> WinKeyLayout {
+    WinKeyLayout::from_reader(File::open("tests/data/test.klc").unwrap()).unwrap()
+}
+
+fn converter(base: Base) -> KlcToXkb {
+    let mut resolver = Resolver::new(Vec::new());
+    resolver.add_file("dk".to_owned(), SymbolsFile::parse(DK).unwrap());
+    KlcToXkb {
+        base,
+        resolver,
+        ..KlcToXkb::default()
+    }
+}
+
+#[test]
+fn against_base() {
+    let conversion = converter(Base::Locale).convert(&layout()).unwrap();
+    let partial = &conversion.layout.default_partial;
+    assert_eq!(partial.includes[0].file, "dk");
+
+    // Only keys that differ from the base are written, filled in from it
+    let keys = &partial.groups[0].keys;
+    assert!(!keys.contains_key(&Key::AE02));
+    assert_eq!(keys[&Key::AE01].altgr, Character::Char('¹'));
+    assert!(!keys.contains_key(&Key::TLDE));
+    assert_eq!(keys[&Key::AC10].altgr, Character::Dead("acute".into()));
+    assert_eq!(keys[&Key::AC10].altgr_shift, Character::Dead("doubleacute".into()));
+
+    assert!(conversion.compose.sequences.iter().any(|s| s.keys == ["dead_acute", "a"] && s.output == "á"));
+    assert_eq!(conversion.warnings, []);
+}
+
+#[test]
+fn standalone() {
+    let conversion = converter(Base::Standalone).convert(&layout()).unwrap();
+    let partial = &conversion.layout.default_partial;
+    // Only the ALTGR attribute is included
+    let includes: Vec<_> = partial.includes.iter().map(|i| i.file.as_str()).collect();
+    assert_eq!(includes, ["level3(ralt_switch)"]);
+    assert_eq!(partial.groups[0].keys[&Key::AE02].shift, Character::Char('"'));
+
+    let conversion = converter(Base::Include("missing".to_owned())).convert(&layout());
+    assert!(conversion.is_err());
+}
+
+#[test]
+fn warnings() {
+    let mut layout = layout();
+    let mut key = layout.layout[&0x10].clone();
+    key.ctrl = Some('x');
+    key.set(3, Some('y'));
+    layout.layout.insert(0x10, key.clone());
+    layout.layout.insert(0x7f, key);
+    layout.deadkeys.insert('x', Some(('a', 'ä'.into())).into_iter().collect());
+
+    let conversion = converter(Base::Standalone).convert(&layout).unwrap();
+    assert_eq!(conversion.warnings, [
+        Warning::DroppedCtrl(0x10),
+        Warning::DroppedState(0x10, 3),
+        Warning::UnmappedScanCode(0x7f),
+        Warning::UnmappedDeadKey('x'),
+    ]);
+    assert!(conversion.compose.sequences.iter().any(|s| s.keys == ["x", "a"] && s.output == "ä"));
+}
+
+#[test]
+fn attributes() {
+    let mut layout = layout();
+    layout.attributes = vec![Attribute::ShiftLock, Attribute::LrmRlm, Attribute::KanaLock];
+
+    let conversion = converter(Base::Locale).convert(&layout).unwrap();
+    let includes: Vec<_> = conversion.layout.default_partial.includes.iter().map(|i| i.file.as_str()).collect();
+    assert_eq!(includes, ["dk", "capslock(shiftlock)"]);
+    assert_eq!(conversion.warnings, [
+        Warning::UnsupportedAttribute(Attribute::LrmRlm),
+        Warning::UnsupportedAttribute(Attribute::KanaLock),
+    ]);
+}
+
+#[test]
+fn scan_codes() {
+    assert_eq!(try_win_to_linux(0x10), Some(Key::AD01));
+    assert_eq!(try_win_to_linux(0x7f), None);
+    assert_eq!(linux_to_win(&Key::AD01), Some(0x10));
+    assert_eq!(linux_to_win(&Key::Other("BKSP".into())), None);
+}
@@ -0,0 +1,71 @@
+//! The optimizer only ever improves a layout and leaves what's pinned alone
+
+use klay::{KlayLayout, KeyboardKey, Level};
+use klay::optimize::{CostModel, Corpus, Optimizer};
+
+const LAYOUT: &str = r#"
+[metadata]
+name = "Test"
+description = "Optimizer test layout"
+short = "tst"
+locale = "da-DK"
+version = "1.0"
+author = "klay"
+
+[keymap]
+e01 = ['1', 'e']
+d01 = ['q', 'Q']
+d02 = ['w', 'W']
+d03 = ['f', 'F']
+d04 = ['p', 'P']
+c01 = ['e', 'E']
+c02 = ['z', 'Z']
+c03 = ['x', 'X']
+c04 = ['t', 'T']
+b01 = ['a', 'A']
+b02 = ['s', 'S']
+b03 = ['h', 'H']
+
+[special]
+"#;
+
+const TEXT: &str = "The seas hate the east; what she wants, she asks the fast ships for. \
+    That is the Test that Sasha sat at the pass.";
+
+fn layout() -> KlayLayout {
+    KlayLayout::from_str(LAYOUT).unwrap()
+}
+
+#[test]
+fn cheapest_slot() {
+    // 'e' is both on the shifted number row and on the home row
+    let model = CostModel::default();
+    let cost = model.cost(&layout(), &Corpus::new("e"));
+    assert_eq!(cost, model.key_effort[&KeyboardKey::C01] + model.level_effort[&Level::Normal]);
+}
+
+#[test]
+fn optimize() {
+    let layout = layout();
+    let corpus = Corpus::new(TEXT);
+    let model = CostModel::default();
+    let before = model.cost(&layout, &corpus);
+
+    let mut optimizer = Optimizer::new(layout.keymap.keys().copied());
+    optimizer.iterations = 5_000;
+    optimizer.seed = 42;
+    optimizer.pin_key(KeyboardKey::C04).pin_level(Level::Shift).pin(KeyboardKey::D01, Level::Normal);
+
+    let (optimized, after) = optimizer.optimize(&layout, &corpus, &model);
+    assert!(after < before, "{} is not below {}", after, before);
+    assert_eq!(model.cost(&optimized, &corpus), after);
+
+    assert_eq!(optimized.keymap[&KeyboardKey::C04], layout.keymap[&KeyboardKey::C04]);
+    assert_eq!(optimized.keymap[&KeyboardKey::D01].get(Level::Normal), layout.keymap[&KeyboardKey::D01].get(Level::Normal));
+    for (key, outs) in &layout.keymap {
+        assert_eq!(optimized.keymap[key].get(Level::Shift), outs.get(Level::Shift));
+    }
+
+    // The same seed finds the same layout
+    assert_eq!(optimizer.optimize(&layout, &corpus, &model), (optimized, after));
+}
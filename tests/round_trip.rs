@@ -0,0 +1,70 @@
+//! Parsing, writing and parsing again must give the same layout, and the
+//! written files must match the golden files next to the inputs.
+
+use std::fs::{read, read_to_string};
+
+fn data(name: &str) -> String {
+    format!("{}/tests/data/{}", env!("CARGO_MANIFEST_DIR"), name)
+}
+
+#[test]
+fn klay() {
+    use klay::KlayLayout;
+
+    let layout = KlayLayout::from_str(&read_to_string(data("test.klay.toml")).unwrap()).unwrap();
+    let written = layout.to_string().unwrap();
+
+    assert_eq!(written, read_to_string(data("test.klay.toml.golden")).unwrap());
+    assert_eq!(KlayLayout::from_str(&written).unwrap(), layout);
+}
+
+#[cfg(feature = "windows")]
+fn klc_file(name: &str) {
+    use klay::klc::WinKeyLayout;
+
+    let layout = WinKeyLayout::from_reader(&read(data(name)).unwrap()[..]).unwrap();
+    let mut written = Vec::new();
+    layout.write(&mut written).unwrap();
+
+    assert_eq!(written, read(data(&format!("{}.golden", name))).unwrap());
+    assert_eq!(WinKeyLayout::from_reader(&written[..]).unwrap(), layout);
+}
+
+#[cfg(feature = "windows")]
+#[test]
+fn klc() {
+    klc_file("test.klc");
+}
+
+/// Without a SHIFTSTATE table none is written back
+#[cfg(feature = "windows")]
+#[test]
+fn klc_default_shift_states() {
+    klc_file("default.klc");
+}
+
+#[cfg(feature = "linux")]
+#[test]
+fn xkb() {
+    use klay::linux::Layout;
+
+    let layout = Layout::from_reader(&read(data("test.xkb")).unwrap()[..]).unwrap();
+    let mut written = Vec::new();
+    layout.write(&mut written).unwrap();
+
+    assert_eq!(String::from_utf8(written.clone()).unwrap(), read_to_string(data("test.xkb.golden")).unwrap());
+    assert_eq!(Layout::from_reader(&written[..]).unwrap(), layout);
+}
+
+#[cfg(feature = "macos")]
+#[test]
+fn keylayout() {
+    use klay::keylayout::{parse, write};
+
+    let layout = parse(&read(data("test.keylayout")).unwrap()[..]).unwrap();
+    let mut written = Vec::new();
+    write(&mut written, layout.clone()).unwrap();
+
+    assert_eq!(String::from_utf8(written.clone()).unwrap(), read_to_string(data("test.keylayout.golden")).unwrap());
+    assert_eq!(parse(&written[..]).unwrap(), layout);
+}
@@ -0,0 +1,74 @@
+//! XCompose files are read with their escapes and written back the same
+
+#![cfg(feature = "linux")]
+
+use klay::linux::{Compose, Sequence};
+
+fn sequence(keys: &[&str], output: &str) -> Sequence {
+    Sequence {
+        keys: keys.iter().map(|&k| k.to_owned()).collect(),
+        output: output.to_owned(),
+    }
+}
+
+#[test]
+fn read() {
+    let compose = Compose::from_reader(r#"# A comment
+include "%L"
+include "/etc/extra"
+
+<dead_acute> <e>	: "é" eacute
+<Multi_key> <e> <equal> : "\xe2\x82\xac" # Escaped bytes of a character
+<Multi_key> <o> <o> : "\303\270"
+<Multi_key> <q> <q> : "\"\\\t"
+<Multi_key> <a> <e> : ae
+"#.as_bytes()).unwrap();
+
+    assert!(compose.include_default);
+    assert_eq!(compose.includes, ["/etc/extra"]);
+    assert_eq!(compose.sequences, [
+        sequence(&["dead_acute", "e"], "é"),
+        sequence(&["Multi_key", "e", "equal"], "€"),
+        sequence(&["Multi_key", "o", "o"], "ø"),
+        sequence(&["Multi_key", "q", "q"], "\"\\\t"),
+        sequence(&["Multi_key", "a", "e"], "æ"),
+    ]);
+
+    assert!(Compose::from_reader(&b"<Multi_key> <x> : \"\\xff\"\n"[..]).is_err());
+    assert!(Compose::from_reader(&b"<Multi_key> <x> \"x\"\n"[..]).is_err());
+}
+
+#[test]
+fn write() {
+    let mut compose = Compose::new();
+    compose.add_deadkey("dead_acute", vec![('e', 'é')]);
+    compose.sequences.push(sequence(&["Multi_key", "q", "q"], "\"\\"));
+    compose.sequences.push(sequence(&["Multi_key", "f", "f"], "ff"));
+
+    let mut written = Vec::new();
+    compose.write(&mut written).unwrap();
+    assert_eq!(String::from_utf8(written.clone()).unwrap(), r#"include "%L"
+
+<dead_acute> <e> : "é" eacute
+<Multi_key> <q> <q> : "\"\\"
+<Multi_key> <f> <f> : "ff"
+"#);
+    assert_eq!(Compose::from_reader(&written[..]).unwrap(), compose);
+}
+
+#[test]
+fn control_characters() {
+    let mut compose = Compose::new();
+    compose.sequences.push(sequence(&["Multi_key", "n", "l"], "one\ntwo"));
+    compose.sequences.push(sequence(&["Multi_key", "t", "t"], "\t\r"));
+    // Digits after an octal escape stay digits
+    compose.sequences.push(sequence(&["Multi_key", "c", "c"], "\u{1}7\u{85}"));
+
+    let mut written = Vec::new();
+    compose.write(&mut written).unwrap();
+    let text = String::from_utf8(written.clone()).unwrap();
+    assert!(text.contains("<Multi_key> <n> <l> : \"one\\ntwo\"\n"), "{}", text);
+    assert!(text.contains("<Multi_key> <t> <t> : \"\\t\\r\"\n"));
+    assert!(text.contains("<Multi_key> <c> <c> : \"\\0017\\302\\205\"\n"));
+    assert_eq!(Compose::from_reader(&written[..]).unwrap(), compose);
+}
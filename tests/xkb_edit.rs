@@ -0,0 +1,127 @@
+//! Editing a symbols file only changes the text of what changes
+
+#![cfg(feature = "linux")]
+
+use klay::linux::{Layout, SymbolsEditor, Key, Character, Output};
+
+use std::fs::{read_dir, read_to_string};
+use std::path::Path;
+
+const SYMBOLS: &str = r#"// A layout with comments
+default partial alphanumeric_keys
+xkb_symbols "basic" {
+    name[Group1]= "Basic";
+
+    key <AE01> {	[         1,     exclam ]	}; // one
+    key <AE02> {	[         2,         at ]	};
+    /* the letters */
+    key <AD01> { type= "ALPHABETIC", [ q, Q ] };
+};
+
+xkb_symbols "empty" {
+};
+"#;
+
+fn lines_changed(a: &str, b: &str) -> usize {
+    let (a, b): (Vec<_>, Vec<_>) = (a.lines().collect(), b.lines().collect());
+    let same = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let same_end = a[same..].iter().rev().zip(b[same..].iter().rev()).take_while(|(x, y)| x == y).count();
+    (a.len() - same - same_end).max(b.len() - same - same_end)
+}
+
+#[test]
+fn edit_one_key() {
+    let mut layout = Layout::from_reader(SYMBOLS.as_bytes()).unwrap();
+    layout.default_partial.groups[0].keys.get_mut(&Key::AE02).unwrap().shift = Character::Char('"');
+
+    let mut editor = SymbolsEditor::new(SYMBOLS.to_owned()).unwrap();
+    editor.update(&layout).unwrap();
+    assert_eq!(lines_changed(SYMBOLS, editor.as_str()), 1);
+    assert!(editor.as_str().contains("    key <AE02> {\t[ 2, quotedbl ]\t};\n"));
+    assert_eq!(Layout::from_reader(editor.as_str().as_bytes()).unwrap(), layout);
+}
+
+#[test]
+fn add_and_remove() {
+    let mut layout = Layout::from_reader(SYMBOLS.as_bytes()).unwrap();
+    let group = &mut layout.default_partial.groups[0];
+    group.keys.remove(&Key::AE01);
+    let q = group.keys[&Key::AD01].clone();
+    group.keys.insert(Key::AD02, q);
+    let one = Output {
+        normal: Character::Char('1'),
+        shift: Character::default(),
+        altgr: Character::default(),
+        altgr_shift: Character::default(),
+    };
+    layout.partials[0].group_mut(2).keys.insert(Key::AE01, one);
+
+    let mut editor = SymbolsEditor::new(SYMBOLS.to_owned()).unwrap();
+    editor.update(&layout).unwrap();
+    let edited = editor.as_str();
+    assert!(!edited.contains("<AE01> {\t"));
+    assert!(edited.contains("/* the letters */\n    key <AD01> { type= \"ALPHABETIC\", [ q, Q ] };\n    key <AD02> { [ q, Q ] };\n};"));
+    assert!(edited.contains("xkb_symbols \"empty\" {\n    key <AE01> { symbols[Group2]= [ 1 ] };\n};"));
+    assert!(edited.starts_with("// A layout with comments\n"));
+    assert_eq!(Layout::from_reader(edited.as_bytes()).unwrap(), layout);
+}
+
+#[test]
+fn remove_section() {
+    let mut editor = SymbolsEditor::new(SYMBOLS.to_owned()).unwrap();
+    assert!(editor.remove_section("empty").unwrap());
+    assert!(!editor.remove_section("empty").unwrap());
+    assert_eq!(editor.as_str(), &SYMBOLS[..SYMBOLS.find("\n\nxkb_symbols \"empty\"").unwrap() + 1]);
+
+    assert!(editor.remove_section("basic").unwrap());
+    assert_eq!(editor.as_str(), "// A layout with comments\n");
+    assert!(editor.file().sections.is_empty());
+}
+
+#[test]
+fn group_names() {
+    let mut layout = Layout::from_reader(SYMBOLS.as_bytes()).unwrap();
+    layout.default_partial.groups[0].name = Some("Changed \"name\"".to_owned());
+
+    let mut editor = SymbolsEditor::new(SYMBOLS.to_owned()).unwrap();
+    editor.update(&layout).unwrap();
+    assert!(editor.as_str().contains("    name[Group1]= \"Changed \\\"name\\\"\";\n"));
+    assert_eq!(lines_changed(SYMBOLS, editor.as_str()), 1);
+}
+
+/// Writing a file's own model into it changes nothing
+#[test]
+fn unchanged() {
+    let dir = Path::new("/usr/share/X11/xkb/symbols");
+    let mut paths = vec![Path::new("tests/data/test.xkb").to_owned()];
+    if dir.is_dir() {
+        paths.extend(read_dir(dir).unwrap().map(|e| e.unwrap().path()).filter(|p| p.is_file() && !p.ends_with("README")));
+    }
+
+    for path in paths {
+        let src = read_to_string(&path).unwrap();
+        let layout = Layout::from_reader(src.as_bytes()).unwrap();
+        let mut editor = SymbolsEditor::new(src.clone()).unwrap();
+        editor.update(&layout).unwrap();
+        assert!(editor.as_str() == src, "{} changed", path.display());
+    }
+}
+
+#[test]
+fn keep_unmodelled_levels() {
+    let src = r#"xkb_symbols "basic" {
+    key <AD01> { type= "EIGHT_LEVEL", [ q, Q, at, U1E9E, a, A, NoSymbol, 0x1002203 ] };
+    key <AD02> { [ { w, W }, W, NoSymbol, NoSymbol ] };
+};
+"#;
+    let mut layout = Layout::from_reader(src.as_bytes()).unwrap();
+    let keys = &mut layout.default_partial.groups[0].keys;
+    keys.get_mut(&Key::AD01).unwrap().altgr = Character::Char('€');
+    keys.get_mut(&Key::AD02).unwrap().altgr = Character::Char('ŵ');
+
+    let mut editor = SymbolsEditor::new(src.to_owned()).unwrap();
+    editor.update(&layout).unwrap();
+    assert!(editor.as_str().contains("[ q, Q, EuroSign, U1E9E, a, A, NoSymbol, 0x1002203 ]"), "{}", editor);
+    assert!(editor.as_str().contains("[ { w, W }, W, wcircumflex ]"), "{}", editor);
+    assert_eq!(Layout::from_reader(editor.as_str().as_bytes()).unwrap(), layout);
+}
@@ -0,0 +1,170 @@
+//! Layouts are installed next to what's already in an XKB directory
+
+#![cfg(feature = "linux")]
+
+use klay::Metadata;
+use klay::linux::{Layout, Installer, RegistryEntry};
+
+use std::env::temp_dir;
+use std::fs::{File, create_dir_all, read_to_string, remove_dir_all, write};
+
+const OTHER: &str = r#"
+default partial alphanumeric_keys
+xkb_symbols "basic" {
+    key <AE01> { [ 1, exclam ] };
+};
+"#;
+
+const REGISTRY: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE xkbConfigRegistry SYSTEM "xkb.dtd">
+<xkbConfigRegistry version="1.1">
+  <layoutList>
+    <layout>
+      <configItem>
+        <name>mine</name>
+        <description>Mine</description>
+      </configItem>
+    </layout>
+  </layoutList>
+  <optionList>
+  </optionList>
+</xkbConfigRegistry>
+"#;
+
+fn install(installer: &Installer, name: &str, layout: &Layout) {
+    let entry = RegistryEntry::new(name, &Metadata::default(), layout);
+    installer.install(name, layout, &entry).unwrap();
+}
+
+#[test]
+fn install_and_uninstall() {
+    let root = temp_dir().join(format!("klay-install-{}", std::process::id()));
+    let installer = Installer::new(root.clone());
+    let layout = Layout::from_reader(File::open("tests/data/test.xkb").unwrap()).unwrap();
+    let other = Layout::from_reader(OTHER.as_bytes()).unwrap();
+
+    install(&installer, "test", &layout);
+    let symbols = Layout::from_reader(File::open(root.join("symbols/test")).unwrap()).unwrap();
+    assert_eq!(symbols, layout);
+
+    // Installing again doesn't add another entry, and other layouts are added to the registry
+    install(&installer, "test", &layout);
+    install(&installer, "other", &other);
+    let registry = read_to_string(installer.registry_path()).unwrap();
+    assert_eq!(registry.matches("<name>test</name>").count(), 1);
+    assert_eq!(registry.matches("<name>other</name>").count(), 1);
+
+    assert!(installer.uninstall("test").unwrap());
+    assert!(!root.join("symbols/test").exists());
+    assert!(!read_to_string(installer.registry_path()).unwrap().contains("<name>test</name>"));
+    assert!(!installer.uninstall("test").unwrap());
+
+    // Sections the new layout doesn't have are kept, and stay once it's uninstalled
+    install(&installer, "test", &layout);
+    install(&installer, "test", &other);
+    let symbols = Layout::from_reader(File::open(root.join("symbols/test")).unwrap()).unwrap();
+    assert_eq!(symbols.default_partial, other.default_partial);
+    assert!(symbols.get_partial("nodeadkeys").is_some());
+    assert!(installer.uninstall("test").unwrap());
+    let symbols = Layout::from_reader(File::open(root.join("symbols/test")).unwrap()).unwrap();
+    assert!(symbols.get_partial("basic").is_none());
+    assert!(symbols.get_partial("nodeadkeys").is_some());
+    assert!(!installer.uninstall("test").unwrap());
+
+    // Nothing is left behind once the last layout is gone
+    assert!(installer.uninstall("other").unwrap());
+    assert!(!installer.registry_path().exists());
+
+    remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn existing_registry() {
+    let root = temp_dir().join(format!("klay-install-registry-{}", std::process::id()));
+    let installer = Installer::new(root.clone());
+    create_dir_all(root.join("rules")).unwrap();
+    write(installer.registry_path(), REGISTRY).unwrap();
+
+    let other = Layout::from_reader(OTHER.as_bytes()).unwrap();
+    install(&installer, "other", &other);
+    let registry = read_to_string(installer.registry_path()).unwrap();
+    assert!(registry.contains("<name>mine</name>"));
+    assert!(registry.contains("<optionList>"));
+    assert!(registry.find("<name>other</name>").unwrap() < registry.find("</layoutList>").unwrap());
+
+    // The file is as it was after uninstalling
+    installer.uninstall("other").unwrap();
+    assert_eq!(read_to_string(installer.registry_path()).unwrap(), REGISTRY);
+
+    remove_dir_all(root).unwrap();
+}
+
+const HAND_WRITTEN: &str = r#"// Written by hand
+partial alphanumeric_keys
+xkb_symbols "extra" {
+    key <AE01> { [ 1, exclam ] }; // One
+    key <CAPS> { actions[Group1] = [ SetMods(modifiers=Shift) ] };
+    modifier_map Shift { <CAPS> };
+};
+
+default partial alphanumeric_keys
+xkb_symbols "basic" {
+    key <AE02> { [ 2, at ] };
+};
+"#;
+
+#[test]
+fn keeps_what_is_written() {
+    let root = temp_dir().join(format!("klay-install-keep-{}", std::process::id()));
+    let installer = Installer::new(root.clone());
+    create_dir_all(root.join("symbols")).unwrap();
+    write(root.join("symbols/test"), HAND_WRITTEN).unwrap();
+
+    let mut other = Layout::from_reader(OTHER.as_bytes()).unwrap();
+    other.default_partial.name = "new".to_owned();
+    install(&installer, "test", &other);
+
+    // Sections the layout doesn't have are kept as they were, only the flag moves
+    let written = read_to_string(root.join("symbols/test")).unwrap();
+    assert!(written.starts_with(&HAND_WRITTEN[..HAND_WRITTEN.find("\ndefault").unwrap()]), "{}", written);
+    assert!(written.contains("\npartial alphanumeric_keys\nxkb_symbols \"basic\" {\n    key <AE02> { [ 2, at ] };\n};\n"));
+    let symbols = Layout::from_reader(written.as_bytes()).unwrap();
+    assert_eq!(symbols.default_partial, other.default_partial);
+    assert!(symbols.get_partial("basic").is_some());
+
+    remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn uninstall_keeps_other_sections() {
+    let root = temp_dir().join(format!("klay-install-uninstall-{}", std::process::id()));
+    let installer = Installer::new(root.clone());
+    create_dir_all(root.join("symbols")).unwrap();
+    write(root.join("symbols/test"), HAND_WRITTEN).unwrap();
+
+    let mut layout = Layout::from_reader(File::open("tests/data/test.xkb").unwrap()).unwrap();
+    layout.default_partial.name = "new".to_owned();
+    install(&installer, "test", &layout);
+    assert!(installer.uninstall("test").unwrap());
+
+    // Only the sections that were there before are left, as they were written
+    let written = read_to_string(root.join("symbols/test")).unwrap();
+    assert_eq!(written, HAND_WRITTEN.replace("default partial", "partial"));
+    assert!(!installer.registry_path().exists());
+
+    remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn names() {
+    let installer = Installer::new(temp_dir().join("klay-install-names"));
+    let layout = Layout::from_reader(OTHER.as_bytes()).unwrap();
+    for &name in ["", ".", "..", "../test", "a/b", "a\\b"].iter() {
+        let entry = RegistryEntry::new(name, &Metadata::default(), &layout);
+        assert!(installer.install(name, &layout, &entry).is_err(), "{:?}", name);
+        assert!(installer.uninstall(name).is_err(), "{:?}", name);
+        assert!(installer.symbols_path(name).is_err(), "{:?}", name);
+    }
+    assert_eq!(installer.symbols_path("test").unwrap(), temp_dir().join("klay-install-names/symbols/test"));
+    assert!(!temp_dir().join("klay-install-names").exists());
+}
@@ -0,0 +1,96 @@
+//! Keymaps are complete without any XKB data installed
+
+#![cfg(feature = "linux")]
+
+use klay::linux::{Layout, Resolver, SymbolsFile, KeyType, builtin_types};
+
+use std::fs::read_to_string;
+
+fn keymap(section: Option<&str>) -> String {
+    let file = SymbolsFile::parse(&read_to_string("tests/data/test.xkb").unwrap()).unwrap();
+    let layout = Layout::from_file(&file).unwrap();
+    let mut resolver = Resolver::new(Vec::new());
+    resolver.add_file("test".to_owned(), file);
+    // The system layout the test layout is based on isn't needed
+    resolver.add_file("latin".to_owned(), SymbolsFile::parse("xkb_symbols \"type2\" {};").unwrap());
+
+    let mut written = Vec::new();
+    layout.write_keymap(&mut written, section, &resolver).unwrap();
+    String::from_utf8(written).unwrap()
+}
+
+#[test]
+fn sections() {
+    let keymap = keymap(Some("nodeadkeys"));
+
+    assert!(keymap.starts_with("xkb_keymap {"));
+    for section in &["xkb_keycodes", "xkb_types", "xkb_compatibility", "xkb_symbols \"nodeadkeys\""] {
+        assert!(keymap.contains(section), "{} missing", section);
+    }
+    // The types written are parsed back the same
+    let start = keymap.find("xkb_types").unwrap();
+    let end = keymap.find("xkb_compatibility").unwrap();
+    let types = KeyType::parse_all(&keymap[start..end]).unwrap();
+    for key_type in builtin_types() {
+        assert!(types.contains(key_type), "{} differs", key_type.name);
+    }
+}
+
+#[test]
+fn symbols() {
+    let keymap = keymap(Some("nodeadkeys"));
+    let line = |key: &str| keymap.lines().find(|l| l.trim_start().starts_with(&format!("key <{}>", key))).unwrap().to_owned();
+
+    // Included keys are there, with every type written out
+    assert!(line("AD01").contains("type[Group1]= \"FOUR_LEVEL_SEMIALPHABETIC\""));
+    assert!(line("AC10").contains("type[Group1]= \"FOUR_LEVEL\""));
+    // Modifiers are added and mapped, right Alt being AltGr since there are four levels
+    assert!(line("LFSH").contains("[ Shift_L ]"));
+    assert!(line("RALT").contains("[ ISO_Level3_Shift ]"));
+    assert!(keymap.contains("modifier_map Mod5 { <RALT> };"));
+    assert!(keymap.contains("modifier_map Shift { <LFSH>, <RTSH> };"));
+}
+
+#[test]
+fn unknown_type() {
+    let layout = Layout::from_reader(&br#"
+        xkb_symbols "basic" {
+            key <AE01> { type= "MADE_UP", [ 1, exclam ] };
+        };
+    "#[..]).unwrap();
+
+    let err = layout.write_keymap(Vec::new(), None, &Resolver::new(Vec::new())).unwrap_err();
+    assert!(err.to_string().contains("MADE_UP"));
+}
+
+#[test]
+fn unknown_type_emulated() {
+    use klay::emulate::Model;
+
+    let layout = Layout::from_reader(&br#"
+        xkb_symbols "basic" {
+            key <AE01> { type= "MADE_UP", [ 1, exclam ] };
+        };
+    "#[..]).unwrap();
+
+    let err = Model::from_xkb(&layout.default_partial).unwrap_err();
+    assert!(err.to_string().contains("MADE_UP"));
+    let custom = KeyType { name: "MADE_UP".to_owned(), ..builtin_types()[1].clone() };
+    assert!(Model::from_xkb_with_types(&layout.default_partial, &[custom]).is_ok());
+}
+
+#[test]
+fn quoted_names() {
+    let mut layout = Layout::from_reader(&br#"xkb_symbols "basic" { key <AE01> { [ 1, exclam ] }; };"#[..]).unwrap();
+    layout.default_partial.group_mut(1).name = Some(r#"Test "quoted" \ name"#.to_owned());
+
+    let escaped = r#"name[Group1]="Test \"quoted\" \\ name";"#;
+    let mut written = Vec::new();
+    layout.write(&mut written).unwrap();
+    assert!(String::from_utf8(written.clone()).unwrap().contains(escaped));
+    assert_eq!(Layout::from_reader(&written[..]).unwrap(), layout);
+
+    let mut keymap = Vec::new();
+    layout.write_keymap(&mut keymap, None, &Resolver::new(Vec::new())).unwrap();
+    assert!(String::from_utf8(keymap).unwrap().contains(escaped));
+}
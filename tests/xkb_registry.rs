@@ -0,0 +1,59 @@
+//! Registry entries are made from the metadata of a layout
+
+#![cfg(feature = "linux")]
+
+use klay::KlayLayout;
+use klay::linux::{Layout, RegistryEntry, locale_codes, write_registry};
+
+use std::fs::{File, read_to_string};
+
+fn entry() -> RegistryEntry {
+    let klay = KlayLayout::from_str(&read_to_string("tests/data/test.klay.toml").unwrap()).unwrap();
+    let layout = Layout::from_reader(File::open("tests/data/test.xkb").unwrap()).unwrap();
+    RegistryEntry::new("test", &klay.metadata, &layout)
+}
+
+#[test]
+fn locales() {
+    let codes = locale_codes;
+    let some = |l: &str, c: &str| (Some(l.to_owned()), Some(c.to_owned()));
+
+    assert_eq!(codes("da-DK"), some("dan", "DK"));
+    assert_eq!(codes("de_CH.UTF-8"), some("deu", "CH"));
+    assert_eq!(codes("sr-Latn-RS"), some("srp", "RS"));
+    assert_eq!(codes("fil-PH"), some("fil", "PH"));
+    assert_eq!(codes("fr"), (Some("fra".to_owned()), None));
+    assert_eq!(codes(""), (None, None));
+    assert_eq!(codes("xx-YY"), (None, Some("YY".to_owned())));
+}
+
+#[test]
+fn xml() {
+    let mut xml = Vec::new();
+    entry().write_xml(&mut xml).unwrap();
+    let xml = String::from_utf8(xml).unwrap();
+
+    assert!(xml.starts_with("    <layout>\n      <configItem>\n        <name>test</name>\n"));
+    assert!(xml.contains("<shortDescription>tst</shortDescription>"));
+    assert!(xml.contains("<description>Test</description>"));
+    assert!(xml.contains("<iso3166Id>DK</iso3166Id>"));
+    assert!(xml.contains("<iso639Id>dan</iso639Id>"));
+    assert!(xml.contains("<name>nodeadkeys</name>"));
+    assert!(xml.trim_end().ends_with("</layout>"));
+
+    let mut document = Vec::new();
+    write_registry(&mut document, &[entry()]).unwrap();
+    let document = String::from_utf8(document).unwrap();
+    assert!(document.contains(&format!("<layoutList>\n{}  </layoutList>", xml)));
+}
+
+#[test]
+fn lst() {
+    let mut lst = Vec::new();
+    entry().write_lst(&mut lst).unwrap();
+    let lst = String::from_utf8(lst).unwrap();
+
+    assert!(lst.starts_with("! layout\n  test            Test\n"));
+    assert!(lst.contains("\n! variant\n"));
+    assert!(lst.contains("  nodeadkeys      test: Test (no dead keys)\n"));
+}
@@ -0,0 +1,99 @@
+//! Includes are found in the roots and merged with their modes
+
+#![cfg(feature = "linux")]
+
+use klay::linux::{Resolver, Key, Character, Layout, SymbolsFile};
+
+use std::env::temp_dir;
+use std::fs::{create_dir_all, write, remove_dir_all};
+
+const BASE: &str = r#"
+default partial alphanumeric_keys
+xkb_symbols "basic" {
+    key <AE01> { [ 1, exclam ] };
+    key <AE02> { [ 2, at, twosuperior ] };
+};
+
+partial alphanumeric_keys
+xkb_symbols "altgr" {
+    key <AE01> { [ NoSymbol, NoSymbol, onesuperior, exclamdown ] };
+    key <AE02> { [ NoSymbol, quotedbl, NoSymbol, oneeighth ] };
+};
+
+partial alphanumeric_keys
+xkb_symbols "loop" {
+    include "base(loop)"
+};
+"#;
+
+const LAYOUT: &str = r#"
+default partial alphanumeric_keys
+xkb_symbols "basic" {
+    include "base(basic)"
+    augment "base(altgr)"
+    key <AB01> { [ z, Z ] };
+};
+"#;
+
+#[test]
+fn merge_modes() {
+    let root = temp_dir().join(format!("klay-resolve-{}", std::process::id()));
+    create_dir_all(root.join("symbols")).unwrap();
+    write(root.join("symbols/base"), BASE).unwrap();
+
+    let resolver = Resolver::new(vec![root.clone()]);
+    let ch = |c| Character::Char(c);
+
+    // Overriding keeps old levels where the new ones aren't set
+    let keys = &resolver.resolve("base+base(altgr)").unwrap()[0].keys;
+    assert_eq!(keys[&Key::AE01].normal, ch('1'));
+    assert_eq!(keys[&Key::AE01].altgr, ch('¹'));
+    assert_eq!(keys[&Key::AE02].shift, ch('"'));
+    assert_eq!(keys[&Key::AE02].altgr, ch('²'));
+
+    // Augmenting only fills in what isn't set
+    let keys = &resolver.resolve("base|base(altgr)").unwrap()[0].keys;
+    assert_eq!(keys[&Key::AE02].shift, ch('@'));
+    assert_eq!(keys[&Key::AE02].altgr_shift, ch('⅛'));
+
+    let layout = Layout::from_reader(LAYOUT.as_bytes()).unwrap();
+    let keys = &resolver.flatten(&layout.default_partial).unwrap()[0].keys;
+    assert_eq!(keys[&Key::AE02].shift, ch('@'));
+    assert_eq!(keys[&Key::AE01].altgr, ch('¹'));
+    assert_eq!(keys[&Key::AB01].normal, ch('z'));
+
+    // A group suffix moves the first group of the file
+    let groups = resolver.resolve("base+base(altgr):2").unwrap();
+    assert_eq!(groups[0].keys[&Key::AE01].altgr, Character::default());
+    assert_eq!(groups[1].keys[&Key::AE01].altgr, ch('¹'));
+
+    assert!(resolver.resolve("base(loop)").is_err());
+    assert!(resolver.resolve("missing").is_err());
+
+    remove_dir_all(root).unwrap();
+}
+
+#[test]
+fn system_layouts() {
+    let resolver = Resolver::from_env();
+    if resolver.load("us").is_err() {
+        eprintln!("no system symbols found, skipping");
+        return;
+    }
+
+    let keys = &resolver.resolve("pc+de(nodeadkeys)+inet(evdev)").unwrap()[0].keys;
+    assert_eq!(keys[&Key::AD06].normal, Character::Char('z'));
+    assert_eq!(keys[&Key::AE02].altgr, Character::Char('²'));
+    assert_eq!(keys[&Key::Other("BKSP".into())].normal, Character::Symbol("BackSpace".into()));
+}
+
+#[test]
+fn locale_layouts() {
+    let mut resolver = Resolver::new(Vec::new());
+    resolver.add_file("dk".to_owned(), SymbolsFile::parse(BASE).unwrap());
+
+    assert_eq!(resolver.layout_for_locale("da-DK").as_deref(), Some("dk"));
+    assert_eq!(resolver.layout_for_locale("da_DK.UTF-8").as_deref(), Some("dk"));
+    assert_eq!(resolver.layout_for_locale("de-DE"), None);
+    assert_eq!(resolver.layout_for_locale("da"), None);
+}
@@ -0,0 +1,46 @@
+//! Every symbols and types file of the system's xkeyboard-config must parse
+
+#![cfg(feature = "linux")]
+
+use klay::linux::{Layout, KeyType};
+
+use std::fs::{read, read_dir};
+use std::path::Path;
+
+fn load_all(dir: &Path, count: &mut usize, load: fn(&[u8]) -> std::io::Result<()>) {
+    for entry in read_dir(dir).unwrap() {
+        let path = entry.unwrap().path();
+        if path.is_dir() {
+            load_all(&path, count, load);
+        } else if path.file_name().unwrap() != "README" {
+            if let Err(e) = load(&read(&path).unwrap()) {
+                panic!("{}: {}", path.display(), e);
+            }
+            *count += 1;
+        }
+    }
+}
+
+#[test]
+fn system_symbols() {
+    let dir = Path::new("/usr/share/X11/xkb/symbols");
+    if !dir.is_dir() {
+        eprintln!("{} not found, skipping", dir.display());
+        return;
+    }
+    let mut count = 0;
+    load_all(dir, &mut count, |src| Layout::from_reader(src).map(drop));
+    assert!(count > 0);
+}
+
+#[test]
+fn system_types() {
+    let dir = Path::new("/usr/share/X11/xkb/types");
+    if !dir.is_dir() {
+        eprintln!("{} not found, skipping", dir.display());
+        return;
+    }
+    let mut count = 0;
+    load_all(dir, &mut count, |src| KeyType::parse_all(std::str::from_utf8(src).unwrap()).map(drop));
+    assert!(count > 0);
+}